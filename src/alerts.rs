@@ -0,0 +1,185 @@
+// alerts.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Alerts: friend requests, new fans, likes, mentions and the like.
+
+use crate::error::PlurkError;
+use crate::plurk::{Plurk, PlurkUser};
+use crate::render::RenderOptions;
+use crate::utils::{base36_encode, WrappedDT};
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Alert {
+    pub posted: Option<WrappedDT>,
+    #[serde(flatten)]
+    pub kind: AlertKind,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum AlertKind {
+    FriendshipRequest {
+        from_user: PlurkUser,
+    },
+    FriendshipPending {
+        to_user: PlurkUser,
+    },
+    NewFan {
+        new_fan: PlurkUser,
+    },
+    FriendshipAccepted {
+        friend_info: PlurkUser,
+    },
+    NewFriend {
+        new_friend: PlurkUser,
+    },
+    PrivatePlurk {
+        owner: PlurkUser,
+        plurk_id: u64,
+    },
+    PlurkLiked {
+        from_user: PlurkUser,
+        plurk_id: u64,
+    },
+    PlurkReplurked {
+        from_user: PlurkUser,
+        plurk_id: u64,
+    },
+    Mentioned {
+        from_user: PlurkUser,
+        plurk_id: u64,
+    },
+    MyResponded {
+        from_user: PlurkUser,
+        plurk_id: u64,
+    },
+    /// Kinds added to the API after this was written.
+    #[serde(other)]
+    Other,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Answer {
+    /// Accept as a friend
+    Accept,
+    /// Deny the friend request
+    Deny,
+    /// Accept as a fan only
+    Fan,
+}
+
+fn who(u: &PlurkUser) -> String {
+    format!("{} (@{})", u.display_name, u.nick_name)
+}
+
+fn url(plurk_id: u64) -> String {
+    format!("https://www.plurk.com/p/{}", base36_encode(plurk_id))
+}
+
+impl AlertKind {
+    /// What happened, in one sentence.
+    pub fn summary(&self) -> String {
+        match self {
+            AlertKind::FriendshipRequest { from_user } => {
+                format!("{} wants to be your friend", who(from_user))
+            }
+            AlertKind::FriendshipPending { to_user } => {
+                format!("Your friend request to {} is pending", who(to_user))
+            }
+            AlertKind::NewFan { new_fan } => format!("{} became your fan", who(new_fan)),
+            AlertKind::FriendshipAccepted { friend_info } => {
+                format!("{} accepted your friend request", who(friend_info))
+            }
+            AlertKind::NewFriend { new_friend } => {
+                format!("{} is now your friend", who(new_friend))
+            }
+            AlertKind::PrivatePlurk { owner, plurk_id } => {
+                format!("{} shared a private plurk {}", who(owner), url(*plurk_id))
+            }
+            AlertKind::PlurkLiked {
+                from_user,
+                plurk_id,
+            } => format!("{} liked your plurk {}", who(from_user), url(*plurk_id)),
+            AlertKind::PlurkReplurked {
+                from_user,
+                plurk_id,
+            } => format!("{} replurked your plurk {}", who(from_user), url(*plurk_id)),
+            AlertKind::Mentioned {
+                from_user,
+                plurk_id,
+            } => format!("{} mentioned you in {}", who(from_user), url(*plurk_id)),
+            AlertKind::MyResponded {
+                from_user,
+                plurk_id,
+            } => format!("{} responded to {}", who(from_user), url(*plurk_id)),
+            AlertKind::Other => String::from("Another kind of alert"),
+        }
+    }
+}
+
+/// Print the active alerts, or with `history` the past ones.
+pub async fn print_alerts(
+    plurk: &Plurk,
+    history: bool,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let alerts = plurk
+        .get_alerts(if history { "getHistory" } else { "getActive" })
+        .await?;
+    if alerts.is_empty() {
+        eprintln!("No alerts");
+        return Ok(());
+    }
+    for alert in &alerts {
+        let time = alert
+            .posted
+            .as_ref()
+            .map(|t| {
+                opts.theme
+                    .timestamp
+                    .paint(&opts.timestamp(&t.0))
+                    .to_string()
+            })
+            .unwrap_or_default();
+        println!(
+            "{}",
+            opts.hanging(&format!("{} {}", time, alert.kind.summary()))
+        );
+    }
+    let requests = alerts
+        .iter()
+        .filter(|a| matches!(a.kind, AlertKind::FriendshipRequest { .. }))
+        .count();
+    if requests > 0 && !history {
+        println!("Answer with `plurk alerts accept|deny|fan <nick>` or `plurk alerts accept-all`");
+    }
+    Ok(())
+}
+
+/// Answer the friend request of `nick`.
+pub async fn answer(plurk: &Plurk, answer: Answer, nick: &str) -> Result<(), PlurkError> {
+    let user = plurk.find_user(nick).await?;
+    let (api, done) = match answer {
+        Answer::Accept => ("addAsFriend", "Accepted as a friend:"),
+        Answer::Deny => ("denyFriendship", "Denied the friend request of"),
+        Answer::Fan => ("addAsFan", "Accepted as a fan:"),
+    };
+    plurk
+        .alerts(api, &[("user_id", user.id.to_string())])
+        .await?;
+    println!("{} {}", done, who(&user));
+    Ok(())
+}
+
+/// Accept every pending friend request, as friends or as fans.
+pub async fn accept_all(plurk: &Plurk, fans: bool) -> Result<(), PlurkError> {
+    let (api, done) = if fans {
+        ("addAllAsFan", "Accepted every request as a fan")
+    } else {
+        ("addAllAsFriends", "Accepted every request as a friend")
+    };
+    plurk.alerts(api, &[]).await?;
+    println!("{}", done);
+    Ok(())
+}
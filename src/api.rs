@@ -0,0 +1,349 @@
+// api.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Typed wrappers of the Plurk APIs used by the commands.
+
+use crate::alerts::Alert;
+use crate::error::PlurkError;
+use crate::plurk::{Plurk, PlurkData, PlurkResponse, PlurkUser};
+use chrono::{DateTime, FixedOffset, Utc};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+pub struct ObjGetPlurks {
+    pub plurks: Option<Vec<PlurkData>>,
+    pub plurk_users: Option<HashMap<u64, PlurkUser>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ObjGetPlurk {
+    pub plurk: PlurkData,
+    pub user: PlurkUser,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ObjGetResponses {
+    pub responses: Vec<PlurkResponse>,
+    pub friends: HashMap<u64, PlurkUser>,
+    pub response_count: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ObjPlurkSearch {
+    #[serde(default)]
+    pub plurks: Vec<PlurkData>,
+    #[serde(default, alias = "users")]
+    pub plurk_users: HashMap<u64, PlurkUser>,
+    #[serde(default)]
+    pub has_more: bool,
+    pub last_offset: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ObjUserSearch {
+    #[serde(default)]
+    pub users: Vec<PlurkUser>,
+    pub counts: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ObjGetBlocks {
+    pub total: u64,
+    #[serde(default)]
+    pub users: Vec<PlurkUser>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct CompletionUser {
+    pub nick_name: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ObjGetPublicProfile {
+    user_info: PlurkUser,
+}
+
+/// The query of the APIs taking no parameters.
+pub const NO_QUERY: [(&str, &str); 0] = [];
+
+/// Format ids the way the Plurk API expects lists: `[1,2,3]`.
+fn id_list(ids: &[u64]) -> String {
+    let ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+    format!("[{}]", ids.join(","))
+}
+
+/// `offset` of the timeline page after one ending with a plurk `posted` then.
+/// The offset is exclusive and only has seconds, so with `inclusive` it is a
+/// second later and the plurks of that second come again; the caller skips
+/// the ones it has seen.
+pub fn timeline_offset(posted: &DateTime<FixedOffset>, inclusive: bool) -> String {
+    let offset = if inclusive {
+        *posted + chrono::Duration::seconds(1)
+    } else {
+        *posted
+    };
+    offset
+        .with_timezone(&Utc)
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string()
+}
+
+impl Plurk {
+    /// Plurks of the timeline newer than `offset` (rfc3339).
+    pub async fn get_plurks(&self, offset: &str, limit: u64) -> Result<ObjGetPlurks, PlurkError> {
+        self.call(
+            "/APP/Polling/getPlurks",
+            &[("offset", offset), ("limit", &limit.to_string())],
+        )
+        .await
+    }
+
+    /// Plurks of the timeline older than `offset` (`%Y-%m-%dT%H:%M:%S`),
+    /// newest first. `filter` is e.g. `only_user` or `only_responded`.
+    pub async fn get_timeline(
+        &self,
+        offset: Option<&str>,
+        limit: u64,
+        filter: Option<&str>,
+    ) -> Result<ObjGetPlurks, PlurkError> {
+        let mut query = vec![("limit", limit.to_string())];
+        if let Some(offset) = offset {
+            query.push(("offset", offset.to_string()));
+        }
+        if let Some(filter) = filter {
+            query.push(("filter", filter.to_string()));
+        }
+        self.call("/APP/Timeline/getPlurks", &query).await
+    }
+
+    pub async fn get_plurk(&self, plurk_id: u64) -> Result<ObjGetPlurk, PlurkError> {
+        self.call("/APP/Timeline/getPlurk", &[("plurk_id", plurk_id)])
+            .await
+    }
+
+    /// Responses of a plurk, starting from the `from_response`th one.
+    pub async fn get_responses(
+        &self,
+        plurk_id: u64,
+        from_response: u64,
+    ) -> Result<ObjGetResponses, PlurkError> {
+        self.call(
+            "/APP/Responses/get",
+            &[
+                ("plurk_id", plurk_id.to_string()),
+                ("from_response", from_response.to_string()),
+            ],
+        )
+        .await
+    }
+
+    /// All responses from the `from_response`th one, fetching as many
+    /// pages as the thread has.
+    pub async fn get_all_responses(
+        &self,
+        plurk_id: u64,
+        from_response: u64,
+    ) -> Result<ObjGetResponses, PlurkError> {
+        let mut all = self.get_responses(plurk_id, from_response).await?;
+        loop {
+            let fetched = from_response + all.responses.len() as u64;
+            if all.responses.is_empty() || all.response_count.is_none_or(|n| fetched >= n) {
+                return Ok(all);
+            }
+            let page = self.get_responses(plurk_id, fetched).await?;
+            if page.responses.is_empty() {
+                return Ok(all);
+            }
+            all.responses.extend(page.responses);
+            all.friends.extend(page.friends);
+            all.response_count = page.response_count;
+        }
+    }
+
+    pub async fn get_public_profile(&self, user_id: u64) -> Result<PlurkUser, PlurkError> {
+        let profile: ObjGetPublicProfile = self
+            .call("/APP/Profile/getPublicProfile", &[("user_id", user_id)])
+            .await?;
+        Ok(profile.user_info)
+    }
+
+    /// Public plurks matching `query`, starting after `offset`.
+    pub async fn plurk_search(
+        &self,
+        query: &str,
+        offset: Option<u64>,
+    ) -> Result<ObjPlurkSearch, PlurkError> {
+        let mut params = vec![("query", query.to_string())];
+        if let Some(offset) = offset {
+            params.push(("offset", offset.to_string()));
+        }
+        self.call("/APP/PlurkSearch/search", &params).await
+    }
+
+    /// Users matching `query`, skipping the first `offset`.
+    pub async fn user_search(&self, query: &str, offset: u64) -> Result<ObjUserSearch, PlurkError> {
+        self.call(
+            "/APP/UserSearch/search",
+            &[("query", query.to_string()), ("offset", offset.to_string())],
+        )
+        .await
+    }
+
+    /// A user by id or nickname.
+    pub async fn find_user(&self, user: &str) -> Result<PlurkUser, PlurkError> {
+        let profile: ObjGetPublicProfile = self
+            .call(
+                "/APP/Profile/getPublicProfile",
+                &[("user_id", user.trim_start_matches('@'))],
+            )
+            .await?;
+        Ok(profile.user_info)
+    }
+
+    /// The user owning the token.
+    pub async fn get_me(&self) -> Result<PlurkUser, PlurkError> {
+        self.call("/APP/Users/me", &NO_QUERY).await
+    }
+
+    /// Nicknames of friends and followed users, keyed by user id.
+    pub async fn get_completion(&self) -> Result<HashMap<u64, CompletionUser>, PlurkError> {
+        self.call("/APP/FriendsFans/getCompletion", &NO_QUERY).await
+    }
+
+    /// One page of `getFriendsByOffset`, `getFansByOffset` or, without a
+    /// user, `getFollowingByOffset`.
+    pub async fn get_relations(
+        &self,
+        api: &str,
+        user_id: Option<u64>,
+        offset: u64,
+        limit: u64,
+    ) -> Result<Vec<PlurkUser>, PlurkError> {
+        let mut params = vec![("offset", offset), ("limit", limit)];
+        if let Some(user_id) = user_id {
+            params.push(("user_id", user_id));
+        }
+        self.call(&format!("/APP/FriendsFans/{}", api), &params)
+            .await
+    }
+
+    /// Call one of the `/APP/FriendsFans/*` APIs changing the relation with a
+    /// user, e.g. `becomeFriend` or `removeAsFriend`.
+    pub async fn friends_fans(
+        &self,
+        api: &str,
+        params: &[(&str, String)],
+    ) -> Result<(), PlurkError> {
+        let _: serde_json::Value = self
+            .call(&format!("/APP/FriendsFans/{}", api), &params)
+            .await?;
+        Ok(())
+    }
+
+    /// Alerts from `getActive` or `getHistory`.
+    pub async fn get_alerts(&self, api: &str) -> Result<Vec<Alert>, PlurkError> {
+        self.call(&format!("/APP/Alerts/{}", api), &NO_QUERY).await
+    }
+
+    /// Call one of the `/APP/Alerts/*` APIs answering friend requests, e.g.
+    /// `addAsFriend` or `addAllAsFan`.
+    pub async fn alerts(&self, api: &str, params: &[(&str, String)]) -> Result<(), PlurkError> {
+        let _: serde_json::Value = self.call(&format!("/APP/Alerts/{}", api), &params).await?;
+        Ok(())
+    }
+
+    /// One page of blocked users, skipping the first `offset`.
+    pub async fn get_blocks(&self, offset: u64) -> Result<ObjGetBlocks, PlurkError> {
+        self.call("/APP/Blocks/get", &[("offset", offset)]).await
+    }
+
+    /// `/APP/Blocks/block` or `unblock`.
+    pub async fn blocks(&self, api: &str, params: &[(&str, String)]) -> Result<(), PlurkError> {
+        let _: serde_json::Value = self.call(&format!("/APP/Blocks/{}", api), &params).await?;
+        Ok(())
+    }
+
+    /// Post a plurk, only visible to `limited_to` if given; `[0]` means
+    /// friends only.
+    pub async fn plurk_add(
+        &self,
+        content: &str,
+        qualifier: &str,
+        limited_to: Option<&[u64]>,
+    ) -> Result<PlurkData, PlurkError> {
+        let mut params = vec![
+            ("content", content.to_string()),
+            ("qualifier", qualifier.to_string()),
+        ];
+        if let Some(ids) = limited_to {
+            params.push(("limited_to", id_list(ids)));
+        }
+        self.call("/APP/Timeline/plurkAdd", &params).await
+    }
+
+    /// Names of the cliques of the user owning the token.
+    pub async fn get_cliques(&self) -> Result<Vec<String>, PlurkError> {
+        self.call("/APP/Cliques/getCliques", &NO_QUERY).await
+    }
+
+    /// Members of a clique.
+    pub async fn get_clique(&self, name: &str) -> Result<Vec<PlurkUser>, PlurkError> {
+        self.call("/APP/Cliques/getClique", &[("clique_name", name)])
+            .await
+    }
+
+    /// Call one of the `/APP/Cliques/*` APIs changing a clique, e.g.
+    /// `createClique` or `add`.
+    pub async fn cliques(&self, api: &str, params: &[(&str, String)]) -> Result<(), PlurkError> {
+        let _: serde_json::Value = self.call(&format!("/APP/Cliques/{}", api), &params).await?;
+        Ok(())
+    }
+
+    pub async fn response_add(
+        &self,
+        plurk_id: u64,
+        content: &str,
+        qualifier: &str,
+    ) -> Result<PlurkResponse, PlurkError> {
+        self.call(
+            "/APP/Responses/responseAdd",
+            &[
+                ("plurk_id", plurk_id.to_string().as_str()),
+                ("content", content),
+                ("qualifier", qualifier),
+            ],
+        )
+        .await
+    }
+
+    /// Call one of the `/APP/Timeline/*` APIs taking a list of plurk ids,
+    /// e.g. `favoritePlurks`, `replurk`, `mutePlurks` or `markAsRead`.
+    pub async fn timeline_ids(&self, api: &str, ids: &[u64]) -> Result<(), PlurkError> {
+        let _: serde_json::Value = self
+            .call(&format!("/APP/Timeline/{}", api), &[("ids", id_list(ids))])
+            .await?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn timeline_offset_in_utc_seconds() {
+        let posted = DateTime::parse_from_rfc2822("Fri, 05 Jun 2009 23:07:13 +0800").unwrap();
+        assert_eq!(timeline_offset(&posted, false), "2009-06-05T15:07:13");
+        // One second later, so the plurks of that second come again.
+        assert_eq!(timeline_offset(&posted, true), "2009-06-05T15:07:14");
+    }
+
+    #[test]
+    fn id_lists() {
+        assert_eq!(id_list(&[]), "[]");
+        // `limited_to` of a plurk for friends only.
+        assert_eq!(id_list(&[0]), "[0]");
+        assert_eq!(id_list(&[3, 1, 2]), "[3,1,2]");
+    }
+}
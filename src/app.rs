@@ -2,79 +2,246 @@
 // Copyright (C) 2022 dephilia <me@dephilia.moe>
 // Distributed under terms of the MIT license.
 
-use crate::comet::PlurkComet;
+use crate::api::NO_QUERY;
+use crate::archive::{record, record_comet, Archive};
+use crate::comet::{CometFeed, PlurkComet};
 use crate::error::PlurkError;
+use crate::images::preview;
 use crate::plurk::{Plurk, PlurkData, PlurkUser};
-use crate::utils::base36_encode;
+use crate::render::RenderOptions;
+use crate::secret::SecretBackend;
+use crate::thread::{thread_order, with_ancestors};
+use crate::time::cache_dateformat;
+use crate::utils::{base36_encode, parse_plurk_id};
 use colored::Colorize;
-use dirs;
 use serde::Deserialize;
 use std::collections::HashMap;
 use std::fs;
-use std::path::PathBuf;
-use terminal_size::{terminal_size, Width};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
 use tokio::signal;
 
-#[derive(Deserialize, Debug)]
-struct ObjGetPlurks {
-    plurks: Option<Vec<PlurkData>>,
-    plurk_users: Option<HashMap<u64, PlurkUser>>,
+/// One line summary of a plurk, as printed by the compact timeline.
+pub fn plurk_line(
+    p: &PlurkData,
+    display_name: &str,
+    name_color: Option<&str>,
+    opts: &RenderOptions,
+) -> String {
+    format!(
+        "{} {}",
+        opts.header(p, display_name, name_color),
+        opts.content(&p.content_raw, &p.content)
+            .replace("\n", "   ")
+    )
 }
 
-pub async fn print_timeline(plurk: Plurk, verbose: bool, limit: u64) -> Result<(), PlurkError> {
+pub async fn print_timeline(
+    plurk: Plurk,
+    verbose: bool,
+    limit: u64,
+    archive: Option<&Archive>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
     let now = chrono::offset::Utc::now();
     let time = now - chrono::Duration::days(1);
     let time = time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
-    let resp = plurk
-        .request_query(
-            "/APP/Polling/getPlurks",
-            &[("offset", &time), ("limit", &limit.to_string())],
-        )
-        .await?;
-    let body = resp
-        .json::<ObjGetPlurks>()
-        .await
-        .map_err(|e| PlurkError::ParseError(e.to_string()))?;
+    let body = plurk.get_plurks(&time, limit).await?;
 
     if let (Some(plurks), Some(plurk_users)) = (body.plurks, body.plurk_users) {
-        for p in plurks {
-            let display_name = &plurk_users
+        record(archive, &plurks, [], plurk_users.values());
+        for p in plurks.iter().filter(|p| !opts.hides(p)) {
+            let user = plurk_users
                 .get(&p.owner_id)
-                .ok_or(PlurkError::ParseError(p.owner_id.to_string()))?
-                .display_name;
-            if verbose {
-                println!(
-                    "Plurk ==> https://www.plurk.com/p/{}",
-                    base36_encode(p.plurk_id)
-                );
-                println!(
-                    "{} {} {}",
-                    p.posted
-                        .with_timezone(&chrono::Local)
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .bright_yellow(),
-                    display_name.bold().bright_blue(),
-                    p.qualifier.black().on_bright_white()
-                );
-                println!("{}", p.content_raw);
-                if let Some((Width(w), _)) = terminal_size() {
-                    println!("{}", "=".repeat(w.into()));
-                }
-            } else {
-                println!(
-                    "{} {} {} {}",
-                    p.posted
-                        .with_timezone(&chrono::Local)
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .bright_yellow(),
-                    display_name.bold().bright_blue(),
-                    p.qualifier.black().on_bright_white(),
-                    p.content_raw.replace("\n", "   ")
-                );
-            }
+                .ok_or(PlurkError::ParseError(p.owner_id.to_string()))?;
+            print_plurk(p, user, verbose, opts).await;
+        }
+    }
+    Ok(())
+}
+
+/// A plurk as listed by the timeline: one line, or with `verbose` its url,
+/// wrapped content and image previews.
+pub async fn print_plurk(p: &PlurkData, user: &PlurkUser, verbose: bool, opts: &RenderOptions) {
+    let name_color = user.name_color.as_deref();
+    if verbose {
+        println!(
+            "Plurk ==> https://www.plurk.com/p/{}",
+            base36_encode(p.plurk_id)
+        );
+        println!("{}", opts.header(p, &user.display_name, name_color));
+        println!("{}", opts.body(&opts.content(&p.content_raw, &p.content)));
+        preview(opts, &p.content).await;
+        println!("{}", opts.separator());
+    } else {
+        let line = plurk_line(p, &user.display_name, name_color, opts);
+        println!("{}", opts.line(&line));
+    }
+}
+
+/// One line about a user: display name, nickname, full name and karma.
+pub fn user_line(u: &PlurkUser, opts: &RenderOptions) -> String {
+    let mut line = format!(
+        "{} @{}",
+        opts.theme.name(&u.display_name, u.name_color.as_deref()),
+        u.nick_name
+    );
+    if let Some(full_name) = u.full_name.as_deref().filter(|n| !n.is_empty()) {
+        line.push_str(&format!(" ({})", full_name));
+    }
+    line.push_str(&format!(" karma {:.1}", u.karma));
+    opts.line(&line)
+}
+
+/// Print a plurk and its responses, as a conversation tree unless `flat`.
+/// With `since_response` only the responses newer than that one are shown,
+/// along with the ones they reply to.
+pub async fn print_show(
+    plurk: Plurk,
+    id: &str,
+    flat: bool,
+    since_response: Option<u64>,
+    archive: Option<&Archive>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let plurk_id =
+        parse_plurk_id(id).ok_or_else(|| PlurkError::InvalidUrl(format!("not a plurk: {}", id)))?;
+    let thread = plurk.get_plurk(plurk_id).await?;
+    let mut body = plurk.get_all_responses(plurk_id, 0).await?;
+    record(
+        archive,
+        [&thread.plurk],
+        &body.responses,
+        body.friends.values().chain([&thread.user]),
+    );
+    let p = thread.plurk;
+
+    println!(
+        "Plurk ==> https://www.plurk.com/p/{}",
+        base36_encode(p.plurk_id)
+    );
+    println!(
+        "{}",
+        opts.header(
+            &p,
+            &thread.user.display_name,
+            thread.user.name_color.as_deref()
+        )
+    );
+    println!("{}", opts.body(&opts.content(&p.content_raw, &p.content)));
+    preview(opts, &p.content).await;
+    println!("{}", opts.rule());
+
+    body.responses
+        .retain(|r| !opts.blocked.contains(&r.user_id));
+    let mut nicks: HashMap<u64, String> = body
+        .friends
+        .iter()
+        .map(|(id, u)| (*id, u.nick_name.clone()))
+        .collect();
+    nicks.insert(thread.user.id, thread.user.nick_name.clone());
+
+    // Filter after building the tree, so newer replies keep what they answer.
+    let keep = match since_response {
+        Some(since) if flat => body.responses.iter().map(|r| r.id > since).collect(),
+        Some(since) => with_ancestors(&body.responses, &nicks, |r| r.id > since),
+        None => vec![true; body.responses.len()],
+    };
+    for (i, depth) in thread_order(&body.responses, &nicks, flat) {
+        if !keep[i] {
+            continue;
         }
+        let r = &body.responses[i];
+        let display_name = match body.friends.get(&r.user_id) {
+            Some(u) => u.display_name.clone(),
+            None => r.user_id.to_string(),
+        };
+        println!("{}", opts.response(r, &display_name, depth));
+        preview(opts, &r.content).await;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize, Debug)]
+struct ObjCheckToken {
+    app_id: Option<u64>,
+    user_id: Option<u64>,
+    issued: Option<String>,
+    device_id: Option<String>,
+    model: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+struct ObjEcho {
+    data: String,
+}
+
+#[derive(Deserialize, Debug)]
+struct ObjCheckTime {
+    timestamp: i64,
+}
+
+fn print_token_info(info: &ObjCheckToken, user: Option<&PlurkUser>) {
+    let show = |v: Option<String>| v.unwrap_or_else(|| String::from("-"));
+    println!("App id:  {}", show(info.app_id.map(|v| v.to_string())));
+    match user {
+        Some(u) => println!("User:    {}", u),
+        None => println!("User:    {}", show(info.user_id.map(|v| v.to_string()))),
+    }
+    println!("Issued:  {}", show(info.issued.clone()));
+    println!("Device:  {}", show(info.device_id.clone()));
+    println!("Model:   {}", show(info.model.clone()));
+}
+
+pub async fn print_auth_status(plurk: Plurk) -> Result<(), PlurkError> {
+    let info: ObjCheckToken = plurk.call("/APP/checkToken", &NO_QUERY).await?;
+    let me = match plurk.get_me().await {
+        Ok(me) => Some(me),
+        Err(e) => {
+            eprintln!("Warning: cannot get the user of the token: {}", e);
+            None
+        }
+    };
+    println!("{}", "Token is valid".green());
+    print_token_info(&info, me.as_ref());
+    Ok(())
+}
+
+/// Revoke the token on the server. The caller is responsible for removing it
+/// from the key file.
+pub async fn expire_auth(plurk: &mut Plurk) -> Result<(), PlurkError> {
+    let info: ObjCheckToken = plurk.call("/APP/expireToken", &NO_QUERY).await?;
+    plurk.clear_token();
+    println!("{}", "Token expired".yellow());
+    print_token_info(&info, None);
+    Ok(())
+}
+
+pub async fn test_auth(plurk: Plurk) -> Result<(), PlurkError> {
+    let payload = format!("plurk-cli {}", chrono::offset::Utc::now().timestamp());
+    let echo: ObjEcho = plurk.call("/APP/echo", &[("data", &payload)]).await?;
+    if echo.data != payload {
+        return Err(PlurkError::ApiError(format!(
+            "echo mismatch: sent {:?}, got {:?}",
+            payload, echo.data
+        )));
+    }
+    println!("{} echo round trip", "OK".green());
+
+    let local = chrono::offset::Utc::now().timestamp();
+    let time: ObjCheckTime = plurk.call("/APP/checkTime", &NO_QUERY).await?;
+    let skew = time.timestamp - local;
+    // oauth1 signatures carry a timestamp, plurk rejects large clock skew
+    if skew.abs() > 60 {
+        println!(
+            "{} clock skew {}s against the server, fix the system time",
+            "WARN".yellow(),
+            skew
+        );
+    } else {
+        println!("{} clock skew {}s", "OK".green(), skew);
     }
     Ok(())
 }
@@ -89,88 +256,171 @@ pub async fn print_me(plurk: Plurk) -> Result<(), PlurkError> {
     Ok(())
 }
 
-#[allow(unreachable_code)]
-pub async fn comet_loop(plurk: Plurk) -> Result<(), PlurkError> {
-    let mut count: u8 = 0;
-    let mut comet = PlurkComet::from_plurk(plurk.clone()).await?;
-    loop {
-        if count > 10 {
-            comet.knock().await?;
-            count = 0;
-        } else {
-            count += 1;
-        }
+/// Post a plurk, only visible to `limited_to` if given.
+pub async fn post(
+    plurk: &Plurk,
+    content: &str,
+    qualifier: &str,
+    limited_to: Option<&[u64]>,
+) -> Result<(), PlurkError> {
+    let p = plurk.plurk_add(content, qualifier, limited_to).await?;
+    println!(
+        "Posted https://www.plurk.com/p/{}",
+        base36_encode(p.plurk_id)
+    );
+    Ok(())
+}
 
-        let cdata = match comet.poll_once_mut().await {
-            Ok(d) => d,
-            Err(e) => {
-                println!("{}", e);
+pub async fn comet_loop(
+    plurk: Plurk,
+    archive: Option<&Archive>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let mut feed = CometFeed::new(plurk.clone());
+    loop {
+        for data in feed.next().await? {
+            record_comet(archive, &data);
+            if data.hidden(opts) {
                 continue;
             }
-        };
-
-        if let Some(datas) = cdata {
-            for data in datas {
-                PlurkComet::print_comet(&plurk, data).await?;
-                if let Some((Width(w), _)) = terminal_size() {
-                    println!("{}", "=".repeat(w.into()));
-                }
-            }
-        };
+            PlurkComet::print_comet(&plurk, data, opts).await?;
+            println!("{}", opts.separator());
+        }
     }
-    Ok(())
 }
 
-pub async fn poll_comet(plurk: Plurk) -> Result<(), PlurkError> {
+pub async fn poll_comet(
+    plurk: Plurk,
+    archive: Option<&Archive>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
     println!("Polling Comet...ctrl+c to exit");
     tokio::select! {
-        output = comet_loop(plurk) => output,
+        output = comet_loop(plurk, archive, opts) => output,
         _ = signal::ctrl_c() => Ok(()),
     }
 }
 
-pub fn gen_key_file(
-    consumer_key: String,
-    consumer_secret: String,
-    token_key: Option<String>,
-    token_secret: Option<String>,
+/// Run the oauth flow, through the local callback server unless `no_browser`.
+pub async fn login(
+    plurk: &mut Plurk,
+    no_browser: bool,
+    no_open: bool,
+    port: u16,
+    timeout: Duration,
 ) -> Result<(), PlurkError> {
-    let plurk = Plurk::new(consumer_key, consumer_secret, token_key, token_secret);
-    let config_dir = match dirs::config_dir() {
-        Some(dir) => dir,
-        None => PathBuf::from("."),
+    if no_browser {
+        plurk.acquire_plurk_key().await
+    } else {
+        plurk
+            .acquire_plurk_key_callback(port, !no_open, timeout)
+            .await
+    }
+}
+
+async fn prompt_line(prompt: &str) -> Result<String, PlurkError> {
+    if !io::stdin().is_terminal() {
+        return Err(PlurkError::NotInteractive(format!(
+            "cannot prompt for {}",
+            prompt.trim_end_matches([':', ' '])
+        )));
+    }
+    print!("{}", prompt);
+    io::stdout()
+        .flush()
+        .map_err(|e| PlurkError::IOError(e.to_string()))?;
+    let mut line = String::new();
+    BufReader::new(tokio::io::stdin())
+        .read_line(&mut line)
+        .await
+        .map_err(|e| PlurkError::IOError(e.to_string()))?;
+    Ok(line.trim().to_string())
+}
+
+pub async fn init(
+    backend: SecretBackend,
+    key_file: &str,
+    consumer_key: Option<String>,
+    consumer_secret: Option<String>,
+    force: bool,
+    no_browser: bool,
+) -> Result<(), PlurkError> {
+    if backend.exists(key_file).await && !force {
+        return Err(PlurkError::IOError(format!(
+            "{} already exists, use --force to overwrite",
+            key_file
+        )));
+    }
+
+    let consumer_key = match consumer_key {
+        Some(k) => k,
+        None => prompt_line("Consumer key: ").await?,
+    };
+    let consumer_secret = match consumer_secret {
+        Some(s) => s,
+        None => {
+            if !io::stdin().is_terminal() {
+                return Err(PlurkError::NotInteractive(String::from(
+                    "cannot prompt for consumer secret",
+                )));
+            }
+            rpassword::prompt_password("Consumer secret: ")
+                .map_err(|e| PlurkError::IOError(e.to_string()))?
+        }
     };
-    let config_dir = config_dir.join("plurk-cli");
-    let display = config_dir.display();
-    fs::create_dir_all(config_dir.clone())
-        .map_err(|_| PlurkError::IOError(format!("Cannot create dir: {}", display)))?;
-
-    let config_file = config_dir.join("key.toml");
-    let display = config_file.display();
-    plurk
-        .to_toml(config_file.clone())
-        .map_err(|_| PlurkError::IOError(format!("Cannot access file: {}", display)))?;
+    if consumer_key.is_empty() || consumer_secret.is_empty() {
+        return Err(PlurkError::IOError(String::from(
+            "consumer key and secret are required",
+        )));
+    }
+
+    let mut plurk = Plurk::new(consumer_key, consumer_secret, None, None);
+    login(&mut plurk, no_browser, false, 0, Duration::from_secs(300)).await?;
+
+    let me = plurk
+        .request("/APP/Users/me")
+        .await?
+        .json::<PlurkUser>()
+        .await
+        .map_err(|e| PlurkError::ParseError(e.to_string()))?;
+
+    if backend != SecretBackend::Keyring {
+        if let Some(dir) = Path::new(key_file).parent() {
+            fs::create_dir_all(dir).map_err(|_| {
+                PlurkError::IOError(format!("Cannot create dir: {}", dir.display()))
+            })?;
+        }
+    }
+    backend.store(&plurk, key_file).await?;
+    cache_dateformat(me.dateformat)?;
+    println!("Authorized as {}", me);
+    println!("Keys saved to {}", key_file);
     Ok(())
 }
 
-pub fn get_key_file() -> Result<PathBuf, PlurkError> {
+/// `<config dir>/plurk-cli`, not created until something is written there.
+pub fn config_dir() -> PathBuf {
     let config_dir = match dirs::config_dir() {
         Some(dir) => dir,
         None => PathBuf::from("."),
     };
-    let config_dir = config_dir.join("plurk-cli");
-    let display = config_dir.display();
-    fs::create_dir_all(config_dir.clone())
-        .map_err(|_| PlurkError::IOError(format!("Cannot create dir: {}", display)))?;
-
-    let config_file = config_dir.join("key.toml");
-    Ok(config_file)
+    config_dir.join("plurk-cli")
 }
 
-pub fn get_key_file_string() -> String {
-    get_key_file()
-        .unwrap()
-        .into_os_string()
-        .into_string()
-        .unwrap()
+/// The default key file, or the one of a named profile. Profile names are
+/// letters, digits, `_` and `-`, so they stay inside `profiles/`.
+pub fn get_key_file(profile: Option<&str>) -> Result<PathBuf, PlurkError> {
+    match profile {
+        Some(name) => {
+            let valid = |c: char| c.is_ascii_alphanumeric() || c == '_' || c == '-';
+            if name.is_empty() || !name.chars().all(valid) {
+                return Err(PlurkError::IOError(format!(
+                    "invalid profile name {:?}, use letters, digits, `_` and `-`",
+                    name
+                )));
+            }
+            Ok(config_dir().join("profiles").join(format!("{}.toml", name)))
+        }
+        None => Ok(config_dir().join("key.toml")),
+    }
 }
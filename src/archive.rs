@@ -0,0 +1,861 @@
+// archive.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Local SQLite copy of the plurks, responses and users we have seen.
+
+use crate::api::timeline_offset;
+use crate::comet::CometContentUnit;
+use crate::error::PlurkError;
+use crate::plurk::{Plurk, PlurkData, PlurkResponse, PlurkUser};
+use crate::render::RenderOptions;
+use crate::utils::{base36_encode, WrappedDT};
+use chrono::{DateTime, FixedOffset, TimeZone, Utc};
+use colored::Colorize;
+use rusqlite::types::Value;
+use rusqlite::{params, params_from_iter, Connection};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Schema changes, applied in order. `PRAGMA user_version` holds how many
+/// have run, so only ever append to this list.
+const MIGRATIONS: &[&str] = &[
+    // 1: initial schema
+    "CREATE TABLE users (
+        id INTEGER PRIMARY KEY,
+        nick_name TEXT NOT NULL,
+        display_name TEXT NOT NULL,
+        full_name TEXT,
+        name_color TEXT,
+        avatar INTEGER,
+        has_profile_image INTEGER NOT NULL DEFAULT 0,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE TABLE plurks (
+        plurk_id INTEGER PRIMARY KEY,
+        owner_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        posted INTEGER NOT NULL,
+        qualifier TEXT NOT NULL,
+        content TEXT NOT NULL,
+        content_raw TEXT NOT NULL,
+        lang TEXT NOT NULL,
+        plurk_type INTEGER NOT NULL,
+        limited_to TEXT,
+        response_count INTEGER NOT NULL,
+        favorite_count INTEGER NOT NULL,
+        replurkers_count INTEGER NOT NULL,
+        last_edited INTEGER,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX plurks_owner ON plurks (owner_id, posted);
+    CREATE INDEX plurks_posted ON plurks (posted);
+    CREATE TABLE responses (
+        id INTEGER PRIMARY KEY,
+        plurk_id INTEGER NOT NULL,
+        user_id INTEGER NOT NULL,
+        posted INTEGER NOT NULL,
+        qualifier TEXT NOT NULL,
+        content TEXT NOT NULL,
+        content_raw TEXT NOT NULL,
+        lang TEXT NOT NULL,
+        last_edited INTEGER,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX responses_plurk ON responses (plurk_id, id);",
+    // 2: full-text search, trigrams so CJK text without spaces matches too
+    "CREATE VIRTUAL TABLE plurks_fts USING fts5 (
+        content_raw, content = 'plurks', content_rowid = 'plurk_id', tokenize = 'trigram'
+    );
+    CREATE TRIGGER plurks_fts_insert AFTER INSERT ON plurks BEGIN
+        INSERT INTO plurks_fts (rowid, content_raw) VALUES (new.plurk_id, new.content_raw);
+    END;
+    CREATE TRIGGER plurks_fts_delete AFTER DELETE ON plurks BEGIN
+        INSERT INTO plurks_fts (plurks_fts, rowid, content_raw)
+            VALUES ('delete', old.plurk_id, old.content_raw);
+    END;
+    CREATE TRIGGER plurks_fts_update AFTER UPDATE OF content_raw ON plurks BEGIN
+        INSERT INTO plurks_fts (plurks_fts, rowid, content_raw)
+            VALUES ('delete', old.plurk_id, old.content_raw);
+        INSERT INTO plurks_fts (rowid, content_raw) VALUES (new.plurk_id, new.content_raw);
+    END;
+    CREATE VIRTUAL TABLE responses_fts USING fts5 (
+        content_raw, content = 'responses', content_rowid = 'id', tokenize = 'trigram'
+    );
+    CREATE TRIGGER responses_fts_insert AFTER INSERT ON responses BEGIN
+        INSERT INTO responses_fts (rowid, content_raw) VALUES (new.id, new.content_raw);
+    END;
+    CREATE TRIGGER responses_fts_delete AFTER DELETE ON responses BEGIN
+        INSERT INTO responses_fts (responses_fts, rowid, content_raw)
+            VALUES ('delete', old.id, old.content_raw);
+    END;
+    CREATE TRIGGER responses_fts_update AFTER UPDATE OF content_raw ON responses BEGIN
+        INSERT INTO responses_fts (responses_fts, rowid, content_raw)
+            VALUES ('delete', old.id, old.content_raw);
+        INSERT INTO responses_fts (rowid, content_raw) VALUES (new.id, new.content_raw);
+    END;
+    INSERT INTO plurks_fts (plurks_fts) VALUES ('rebuild');
+    INSERT INTO responses_fts (responses_fts) VALUES ('rebuild');",
+];
+
+/// Trigram search needs at least this many characters per term, shorter
+/// ones are matched with `LIKE`.
+const MIN_FTS_TERM: usize = 3;
+
+/// Plurks per page of `/APP/Timeline/getPlurks`.
+const SYNC_PAGE: u64 = 30;
+
+pub struct Archive {
+    conn: Connection,
+    path: PathBuf,
+}
+
+#[derive(Debug)]
+pub struct ArchiveStats {
+    pub plurks: u64,
+    pub own_plurks: u64,
+    pub responses: u64,
+    pub users: u64,
+    pub oldest: Option<DateTime<Utc>>,
+    pub newest: Option<DateTime<Utc>>,
+    pub size: u64,
+}
+
+/// Restrictions of an archive search besides the query.
+#[derive(Debug, Default)]
+pub struct SearchFilter {
+    /// Nickname of the author.
+    pub author: Option<String>,
+    pub qualifier: Option<String>,
+    /// Unix time range, `until` excluded.
+    pub since: Option<i64>,
+    pub until: Option<i64>,
+    pub responses_only: bool,
+    pub limit: u64,
+}
+
+/// What the archive holds of a plurk, to compare with the live one.
+#[derive(Debug)]
+pub struct Snapshot {
+    pub plurk_id: u64,
+    pub posted: i64,
+    pub content_raw: String,
+    pub last_edited: Option<i64>,
+    pub response_count: u64,
+}
+
+/// A plurk or response matching a search, with its author.
+pub enum Hit {
+    Plurk {
+        plurk: Box<PlurkData>,
+        name: String,
+        name_color: Option<String>,
+    },
+    Response {
+        response: PlurkResponse,
+        name: String,
+    },
+}
+
+impl Hit {
+    fn posted(&self) -> i64 {
+        match self {
+            Hit::Plurk { plurk, .. } => plurk.posted.timestamp(),
+            Hit::Response { response, .. } => response.posted.timestamp(),
+        }
+    }
+}
+
+fn db_err(e: rusqlite::Error) -> PlurkError {
+    PlurkError::DatabaseError(e.to_string())
+}
+
+fn edited(t: &Option<WrappedDT>) -> Option<i64> {
+    t.as_ref().map(|t| t.0.timestamp())
+}
+
+/// A unix timestamp stored in the archive.
+fn from_timestamp(secs: i64) -> DateTime<FixedOffset> {
+    Utc.timestamp_opt(secs, 0)
+        .single()
+        .unwrap_or_default()
+        .fixed_offset()
+}
+
+/// SQL conditions and their parameters for the query terms and filters.
+/// `table` is `plurks` or `responses`, aliased as `t`, with its author
+/// joined as `u`.
+fn search_conditions(table: &str, terms: &[String], filter: &SearchFilter) -> (String, Vec<Value>) {
+    let (key, fts) = match table {
+        "plurks" => ("plurk_id", "plurks_fts"),
+        _ => ("id", "responses_fts"),
+    };
+    let mut conds = Vec::new();
+    let mut values = Vec::new();
+    for term in terms {
+        if term.chars().count() >= MIN_FTS_TERM {
+            conds.push(format!(
+                "t.{} IN (SELECT rowid FROM {} WHERE {} MATCH ?)",
+                key, fts, fts
+            ));
+            values.push(Value::Text(format!("\"{}\"", term.replace('"', "\"\""))));
+        } else {
+            conds.push(String::from("t.content_raw LIKE ? ESCAPE '\\'"));
+            let escaped = term
+                .replace('\\', "\\\\")
+                .replace('%', "\\%")
+                .replace('_', "\\_");
+            values.push(Value::Text(format!("%{}%", escaped)));
+        }
+    }
+    if let Some(author) = &filter.author {
+        conds.push(String::from("u.nick_name = ? COLLATE NOCASE"));
+        values.push(Value::Text(author.trim_start_matches('@').to_string()));
+    }
+    if let Some(qualifier) = &filter.qualifier {
+        conds.push(String::from("t.qualifier = ?"));
+        values.push(Value::Text(qualifier.clone()));
+    }
+    if let Some(since) = filter.since {
+        conds.push(String::from("t.posted >= ?"));
+        values.push(Value::Integer(since));
+    }
+    if let Some(until) = filter.until {
+        conds.push(String::from("t.posted < ?"));
+        values.push(Value::Integer(until));
+    }
+    if conds.is_empty() {
+        conds.push(String::from("1"));
+    }
+    (conds.join(" AND "), values)
+}
+
+impl Archive {
+    /// `archive.db` in the data dir, e.g. `~/.local/share/plurk-cli`.
+    pub fn default_path() -> PathBuf {
+        dirs::data_dir()
+            .unwrap_or_else(|| PathBuf::from("."))
+            .join("plurk-cli")
+            .join("archive.db")
+    }
+
+    pub fn open() -> Result<Self, PlurkError> {
+        Self::open_at(&Self::default_path())
+    }
+
+    pub fn open_at(path: &Path) -> Result<Self, PlurkError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| PlurkError::IOError(e.to_string()))?;
+        }
+        let mut conn = Connection::open(path).map_err(db_err)?;
+        conn.pragma_update(None, "journal_mode", "WAL")
+            .map_err(db_err)?;
+        Self::migrate(&mut conn)?;
+        Ok(Archive {
+            conn,
+            path: path.to_path_buf(),
+        })
+    }
+
+    fn migrate(conn: &mut Connection) -> Result<(), PlurkError> {
+        let version: usize = conn
+            .pragma_query_value(None, "user_version", |row| row.get(0))
+            .map_err(db_err)?;
+        if version > MIGRATIONS.len() {
+            return Err(PlurkError::DatabaseError(format!(
+                "schema version {} is newer than this plurk-cli",
+                version
+            )));
+        }
+        for (i, sql) in MIGRATIONS.iter().enumerate().skip(version) {
+            let tx = conn.transaction().map_err(db_err)?;
+            tx.execute_batch(sql).map_err(db_err)?;
+            tx.pragma_update(None, "user_version", i + 1)
+                .map_err(db_err)?;
+            tx.commit().map_err(db_err)?;
+        }
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn save_user(&self, u: &PlurkUser) -> Result<(), PlurkError> {
+        self.conn
+            .execute(
+                "INSERT INTO users (id, nick_name, display_name, full_name, name_color,
+                    avatar, has_profile_image, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, unixepoch())
+                ON CONFLICT (id) DO UPDATE SET
+                    nick_name = excluded.nick_name,
+                    display_name = excluded.display_name,
+                    full_name = excluded.full_name,
+                    name_color = excluded.name_color,
+                    avatar = excluded.avatar,
+                    has_profile_image = excluded.has_profile_image,
+                    updated_at = excluded.updated_at",
+                params![
+                    u.id,
+                    u.nick_name,
+                    u.display_name,
+                    u.full_name,
+                    u.name_color,
+                    u.avatar,
+                    u.has_profile_image,
+                ],
+            )
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    pub fn save_plurk(&self, p: &PlurkData) -> Result<(), PlurkError> {
+        self.conn
+            .execute(
+                "INSERT INTO plurks (plurk_id, owner_id, user_id, posted, qualifier, content,
+                    content_raw, lang, plurk_type, limited_to, response_count, favorite_count,
+                    replurkers_count, last_edited, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, unixepoch())
+                ON CONFLICT (plurk_id) DO UPDATE SET
+                    qualifier = excluded.qualifier,
+                    content = excluded.content,
+                    content_raw = excluded.content_raw,
+                    lang = excluded.lang,
+                    plurk_type = excluded.plurk_type,
+                    limited_to = excluded.limited_to,
+                    response_count = max(response_count, excluded.response_count),
+                    favorite_count = excluded.favorite_count,
+                    replurkers_count = excluded.replurkers_count,
+                    last_edited = excluded.last_edited,
+                    updated_at = excluded.updated_at",
+                params![
+                    p.plurk_id,
+                    p.owner_id,
+                    p.user_id,
+                    p.posted.timestamp(),
+                    p.qualifier,
+                    p.content,
+                    p.content_raw,
+                    p.lang,
+                    p.plurk_type,
+                    p.limited_to,
+                    p.response_count,
+                    p.favorite_count,
+                    p.replurkers_count,
+                    edited(&p.last_edited),
+                ],
+            )
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    pub fn save_response(&self, r: &PlurkResponse) -> Result<(), PlurkError> {
+        self.conn
+            .execute(
+                "INSERT INTO responses (id, plurk_id, user_id, posted, qualifier, content,
+                    content_raw, lang, last_edited, updated_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, unixepoch())
+                ON CONFLICT (id) DO UPDATE SET
+                    qualifier = excluded.qualifier,
+                    content = excluded.content,
+                    content_raw = excluded.content_raw,
+                    lang = excluded.lang,
+                    last_edited = excluded.last_edited,
+                    updated_at = excluded.updated_at",
+                params![
+                    r.id,
+                    r.plurk_id,
+                    r.user_id,
+                    r.posted.timestamp(),
+                    r.qualifier,
+                    r.content,
+                    r.content_raw,
+                    r.lang,
+                    edited(&r.last_edited),
+                ],
+            )
+            .map_err(db_err)?;
+        Ok(())
+    }
+
+    /// Save plurks, responses and users in one transaction.
+    pub fn save<'a>(
+        &self,
+        plurks: impl IntoIterator<Item = &'a PlurkData>,
+        responses: impl IntoIterator<Item = &'a PlurkResponse>,
+        users: impl IntoIterator<Item = &'a PlurkUser>,
+    ) -> Result<(), PlurkError> {
+        let tx = self.conn.unchecked_transaction().map_err(db_err)?;
+        for u in users {
+            self.save_user(u)?;
+        }
+        for p in plurks {
+            self.save_plurk(p)?;
+        }
+        for r in responses {
+            self.save_response(r)?;
+        }
+        tx.commit().map_err(db_err)
+    }
+
+    /// Responses archived for a plurk.
+    pub fn response_count(&self, plurk_id: u64) -> Result<u64, PlurkError> {
+        self.conn
+            .query_row(
+                "SELECT count(*) FROM responses WHERE plurk_id = ?1",
+                [plurk_id],
+                |row| row.get(0),
+            )
+            .map_err(db_err)
+    }
+
+    /// Plurks and responses containing all `terms`, newest first.
+    pub fn search(&self, terms: &[String], filter: &SearchFilter) -> Result<Vec<Hit>, PlurkError> {
+        let mut hits = Vec::new();
+        if !filter.responses_only {
+            let (conds, mut values) = search_conditions("plurks", terms, filter);
+            values.push(Value::Integer(filter.limit as i64));
+            let sql = format!(
+                "SELECT t.plurk_id, t.owner_id, t.user_id, t.posted, t.qualifier, t.content,
+                    t.content_raw, t.lang, t.plurk_type, t.limited_to, t.response_count,
+                    t.favorite_count, t.replurkers_count, u.display_name, u.name_color
+                FROM plurks t LEFT JOIN users u ON u.id = t.owner_id
+                WHERE {} ORDER BY t.posted DESC LIMIT ?",
+                conds
+            );
+            let mut stmt = self.conn.prepare(&sql).map_err(db_err)?;
+            let rows = stmt
+                .query_map(params_from_iter(values), |row| {
+                    let plurk = Box::new(PlurkData {
+                        plurk_id: row.get(0)?,
+                        owner_id: row.get(1)?,
+                        user_id: row.get(2)?,
+                        posted: from_timestamp(row.get(3)?),
+                        qualifier: row.get(4)?,
+                        content: row.get(5)?,
+                        content_raw: row.get(6)?,
+                        lang: row.get(7)?,
+                        plurk_type: row.get(8)?,
+                        limited_to: row.get(9)?,
+                        response_count: row.get(10)?,
+                        favorite_count: row.get(11)?,
+                        replurkers_count: row.get(12)?,
+                        ..Default::default()
+                    });
+                    let name: Option<String> = row.get(13)?;
+                    Ok(Hit::Plurk {
+                        name: name.unwrap_or_else(|| plurk.owner_id.to_string()),
+                        name_color: row.get(14)?,
+                        plurk,
+                    })
+                })
+                .map_err(db_err)?;
+            for hit in rows {
+                hits.push(hit.map_err(db_err)?);
+            }
+        }
+
+        let (conds, mut values) = search_conditions("responses", terms, filter);
+        values.push(Value::Integer(filter.limit as i64));
+        let sql = format!(
+            "SELECT t.id, t.plurk_id, t.user_id, t.posted, t.qualifier, t.content,
+                t.content_raw, t.lang, u.display_name
+            FROM responses t LEFT JOIN users u ON u.id = t.user_id
+            WHERE {} ORDER BY t.posted DESC LIMIT ?",
+            conds
+        );
+        let mut stmt = self.conn.prepare(&sql).map_err(db_err)?;
+        let rows = stmt
+            .query_map(params_from_iter(values), |row| {
+                let response = PlurkResponse {
+                    id: row.get(0)?,
+                    plurk_id: row.get(1)?,
+                    user_id: row.get(2)?,
+                    posted: from_timestamp(row.get(3)?),
+                    qualifier: row.get(4)?,
+                    content: row.get(5)?,
+                    content_raw: row.get(6)?,
+                    lang: row.get(7)?,
+                    ..Default::default()
+                };
+                let name: Option<String> = row.get(8)?;
+                Ok(Hit::Response {
+                    name: name.unwrap_or_else(|| response.user_id.to_string()),
+                    response,
+                })
+            })
+            .map_err(db_err)?;
+        for hit in rows {
+            hits.push(hit.map_err(db_err)?);
+        }
+
+        hits.sort_by_key(|hit| std::cmp::Reverse(hit.posted()));
+        hits.truncate(filter.limit as usize);
+        Ok(hits)
+    }
+
+    /// Archived plurks of a user, newest first.
+    pub fn snapshots(&self, owner_id: u64) -> Result<Vec<Snapshot>, PlurkError> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT plurk_id, posted, content_raw, last_edited, response_count
+                FROM plurks WHERE owner_id = ?1 ORDER BY posted DESC",
+            )
+            .map_err(db_err)?;
+        let rows = stmt
+            .query_map([owner_id], |row| {
+                Ok(Snapshot {
+                    plurk_id: row.get(0)?,
+                    posted: row.get(1)?,
+                    content_raw: row.get(2)?,
+                    last_edited: row.get(3)?,
+                    response_count: row.get(4)?,
+                })
+            })
+            .map_err(db_err)?;
+        rows.collect::<Result<_, _>>().map_err(db_err)
+    }
+
+    pub fn stats(&self, owner_id: Option<u64>) -> Result<ArchiveStats, PlurkError> {
+        let count = |sql: &str| -> Result<u64, PlurkError> {
+            self.conn
+                .query_row(sql, [], |row| row.get(0))
+                .map_err(db_err)
+        };
+        let (oldest, newest): (Option<i64>, Option<i64>) = self
+            .conn
+            .query_row("SELECT min(posted), max(posted) FROM plurks", [], |row| {
+                Ok((row.get(0)?, row.get(1)?))
+            })
+            .map_err(db_err)?;
+        let own_plurks = match owner_id {
+            Some(id) => self
+                .conn
+                .query_row(
+                    "SELECT count(*) FROM plurks WHERE owner_id = ?1",
+                    [id],
+                    |row| row.get(0),
+                )
+                .map_err(db_err)?,
+            None => 0,
+        };
+        let at = |secs: Option<i64>| secs.and_then(|s| Utc.timestamp_opt(s, 0).single());
+        Ok(ArchiveStats {
+            plurks: count("SELECT count(*) FROM plurks")?,
+            own_plurks,
+            responses: count("SELECT count(*) FROM responses")?,
+            users: count("SELECT count(*) FROM users")?,
+            oldest: at(oldest),
+            newest: at(newest),
+            size: fs::metadata(&self.path).map(|m| m.len()).unwrap_or(0),
+        })
+    }
+}
+
+/// Save what a command has seen, only warning when the archive fails so
+/// printing goes on.
+pub fn record<'a>(
+    archive: Option<&Archive>,
+    plurks: impl IntoIterator<Item = &'a PlurkData>,
+    responses: impl IntoIterator<Item = &'a PlurkResponse>,
+    users: impl IntoIterator<Item = &'a PlurkUser>,
+) {
+    if let Some(archive) = archive {
+        if let Err(e) = archive.save(plurks, responses, users) {
+            eprintln!("Warning: {}", e);
+        }
+    }
+}
+
+/// Save the plurks, responses and users of a comet event.
+pub fn record_comet(archive: Option<&Archive>, data: &CometContentUnit) {
+    match data {
+        CometContentUnit::Response {
+            plurk_data,
+            response,
+            user,
+            ..
+        } => record(archive, [&**plurk_data], [response], user.values()),
+        CometContentUnit::Plurk(p) => record(archive, [p], [], []),
+        CometContentUnit::Notification { .. } => {}
+    }
+}
+
+/// Backfill the archive with the plurks of the user owning the token and
+/// their responses, from the newest back to the first one.
+pub async fn sync(
+    plurk: &Plurk,
+    archive: &Archive,
+    limit: Option<u64>,
+    responses: bool,
+) -> Result<(), PlurkError> {
+    let me = plurk.get_me().await?;
+    archive.save_user(&me)?;
+
+    let mut offset: Option<String> = None;
+    let mut seen = HashSet::new();
+    let mut synced = 0;
+    let mut fetched_responses = 0;
+    while limit.is_none_or(|n| synced < n) {
+        let page = plurk
+            .get_timeline(offset.as_deref(), SYNC_PAGE, Some("only_user"))
+            .await?;
+        let mut plurks = page.plurks.unwrap_or_default();
+        let Some(last) = plurks.last().map(|p| p.posted) else {
+            break;
+        };
+        plurks.retain(|p| seen.insert(p.plurk_id));
+        // A page all seen is a single second, step past it.
+        offset = Some(timeline_offset(&last, !plurks.is_empty()));
+        let users = page.plurk_users.unwrap_or_default();
+        archive.save(&plurks, [], users.values())?;
+
+        for p in &plurks {
+            if limit.is_some_and(|n| synced >= n) {
+                break;
+            }
+            synced += 1;
+            // Only fetch threads that grew since they were archived.
+            if !responses || archive.response_count(p.plurk_id)? >= p.response_count {
+                continue;
+            }
+            let body = plurk.get_all_responses(p.plurk_id, 0).await?;
+            fetched_responses += body.responses.len();
+            archive.save([], &body.responses, body.friends.values())?;
+        }
+        println!(
+            "Synced {} plurks, {} responses (back to {})",
+            synced,
+            fetched_responses,
+            last.format("%Y-%m-%d")
+        );
+    }
+    Ok(())
+}
+
+/// Compare the archived plurks of the user owning the token with the live
+/// timeline, reporting the deleted and edited ones and those with new
+/// responses. With `limit` only that many recent plurks are compared.
+pub async fn diff(
+    plurk: &Plurk,
+    archive: &Archive,
+    limit: Option<u64>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let me = plurk.get_me().await?;
+    let mut snapshots: HashMap<u64, Snapshot> = archive
+        .snapshots(me.id)?
+        .into_iter()
+        .map(|s| (s.plurk_id, s))
+        .collect();
+    let Some(oldest) = snapshots.values().map(|s| s.posted).min() else {
+        println!("None of your plurks are archived, run `plurk archive sync` or `plurk import`");
+        return Ok(());
+    };
+    let line = |kind: String, plurk_id: u64, posted: i64, content_raw: &str| {
+        println!(
+            "{}",
+            opts.line(&format!(
+                "{} {} {} {}",
+                kind,
+                base36_encode(plurk_id),
+                opts.theme
+                    .timestamp
+                    .paint(&opts.timestamp(&from_timestamp(posted))),
+                content_raw.replace('\n', " ")
+            ))
+        )
+    };
+
+    let mut offset: Option<String> = None;
+    let mut seen = HashSet::new();
+    let mut compared = 0;
+    let (mut edits, mut grown) = (0, 0);
+    // Posting time of the oldest live plurk compared; archived plurks since
+    // then that weren't seen are gone.
+    let mut reached = i64::MAX;
+    'pages: loop {
+        let page = plurk
+            .get_timeline(offset.as_deref(), SYNC_PAGE, Some("only_user"))
+            .await?;
+        let mut plurks = page.plurks.unwrap_or_default();
+        let Some(last) = plurks.last().map(|p| p.posted) else {
+            reached = i64::MIN;
+            break;
+        };
+        plurks.retain(|p| seen.insert(p.plurk_id));
+        // A page all seen is a single second, step past it.
+        offset = Some(timeline_offset(&last, !plurks.is_empty()));
+        for p in &plurks {
+            if limit.is_some_and(|n| compared >= n) {
+                break 'pages;
+            }
+            compared += 1;
+            reached = p.posted.timestamp();
+            let Some(s) = snapshots.remove(&p.plurk_id) else {
+                continue;
+            };
+            if edited(&p.last_edited) > s.last_edited {
+                edits += 1;
+                line(
+                    "edited ".yellow().to_string(),
+                    p.plurk_id,
+                    s.posted,
+                    &p.content_raw,
+                );
+            }
+            if p.response_count > s.response_count {
+                grown += 1;
+                let new = format!("+{:<6}", p.response_count - s.response_count);
+                line(
+                    new.green().to_string(),
+                    p.plurk_id,
+                    s.posted,
+                    &p.content_raw,
+                );
+            }
+        }
+        if reached < oldest {
+            break;
+        }
+    }
+
+    let mut deleted: Vec<Snapshot> = snapshots
+        .into_values()
+        .filter(|s| s.posted >= reached)
+        .collect();
+    deleted.sort_by_key(|s| std::cmp::Reverse(s.posted));
+    for s in &deleted {
+        line(
+            "deleted".red().to_string(),
+            s.plurk_id,
+            s.posted,
+            &s.content_raw,
+        );
+    }
+    println!(
+        "Compared {} plurks: {} deleted, {} edited, {} with new responses",
+        compared,
+        deleted.len(),
+        edits,
+        grown
+    );
+    Ok(())
+}
+
+pub async fn print_stats(plurk: &Plurk, archive: &Archive) -> Result<(), PlurkError> {
+    let me = plurk.get_me().await.ok();
+    let stats = archive.stats(me.as_ref().map(|u| u.id))?;
+    let day = |t: Option<DateTime<Utc>>| {
+        t.map(|t| t.format("%Y-%m-%d").to_string())
+            .unwrap_or_else(|| String::from("-"))
+    };
+    println!("Archive:    {}", archive.path().display());
+    println!("Size:       {} KiB", stats.size / 1024);
+    println!(
+        "Plurks:     {} ({} your own)",
+        stats.plurks, stats.own_plurks
+    );
+    println!("Responses:  {}", stats.responses);
+    println!("Users:      {}", stats.users);
+    println!("Range:      {} to {}", day(stats.oldest), day(stats.newest));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn memory() -> Archive {
+        Archive::open_at(Path::new(":memory:")).unwrap()
+    }
+
+    fn plurk(plurk_id: u64, posted: i64, content_raw: &str) -> PlurkData {
+        PlurkData {
+            plurk_id,
+            owner_id: 1,
+            user_id: 1,
+            posted: from_timestamp(posted),
+            qualifier: String::from("says"),
+            content_raw: content_raw.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn ids(hits: &[Hit]) -> Vec<u64> {
+        hits.iter()
+            .map(|h| match h {
+                Hit::Plurk { plurk, .. } => plurk.plurk_id,
+                Hit::Response { response, .. } => response.id,
+            })
+            .collect()
+    }
+
+    fn search(archive: &Archive, terms: &[&str]) -> Vec<u64> {
+        let terms: Vec<String> = terms.iter().map(|t| t.to_string()).collect();
+        let filter = SearchFilter {
+            limit: 20,
+            ..Default::default()
+        };
+        ids(&archive.search(&terms, &filter).unwrap())
+    }
+
+    #[test]
+    fn migrations_run_once() {
+        let mut archive = memory();
+        let version = |conn: &Connection| -> usize {
+            conn.pragma_query_value(None, "user_version", |row| row.get(0))
+                .unwrap()
+        };
+        assert_eq!(version(&archive.conn), MIGRATIONS.len());
+        Archive::migrate(&mut archive.conn).unwrap();
+        assert_eq!(version(&archive.conn), MIGRATIONS.len());
+
+        archive
+            .conn
+            .pragma_update(None, "user_version", MIGRATIONS.len() + 1)
+            .unwrap();
+        assert!(Archive::migrate(&mut archive.conn).is_err());
+    }
+
+    #[test]
+    fn search_long_terms_by_trigrams() {
+        let archive = memory();
+        let plurks = [
+            plurk(1, 100, "今天噗浪好熱鬧"),
+            plurk(2, 200, "hello plurk world"),
+            plurk(3, 300, "plurking again"),
+        ];
+        archive.save(&plurks, [], []).unwrap();
+        assert_eq!(search(&archive, &["噗浪好"]), [1]);
+        assert_eq!(search(&archive, &["plurk"]), [3, 2]);
+        assert_eq!(search(&archive, &["plurk", "world"]), [2]);
+        assert!(search(&archive, &["missing"]).is_empty());
+    }
+
+    #[test]
+    fn search_short_terms_with_like() {
+        let archive = memory();
+        let plurks = [
+            plurk(1, 100, "今天噗浪好熱鬧"),
+            plurk(2, 200, "100% sure"),
+            plurk(3, 300, "a_b"),
+        ];
+        archive.save(&plurks, [], []).unwrap();
+        // Below the trigram length, so matched with `LIKE`.
+        assert_eq!(search(&archive, &["噗浪"]), [1]);
+        assert_eq!(search(&archive, &["%"]), [2]);
+        assert_eq!(search(&archive, &["_"]), [3]);
+        assert_eq!(search(&archive, &["噗浪", "熱鬧"]), [1]);
+    }
+
+    #[test]
+    fn search_follows_edits() {
+        let archive = memory();
+        archive.save([&plurk(1, 100, "old words")], [], []).unwrap();
+        archive.save([&plurk(1, 100, "new words")], [], []).unwrap();
+        assert!(search(&archive, &["old"]).is_empty());
+        assert_eq!(search(&archive, &["new"]), [1]);
+    }
+}
@@ -0,0 +1,105 @@
+// blocks.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Blocked users, mirrored locally so they are hidden right away.
+
+use crate::app::user_line;
+use crate::error::PlurkError;
+use crate::plurk::Plurk;
+use crate::render::RenderOptions;
+use std::collections::HashSet;
+use std::fs;
+use std::path::PathBuf;
+
+/// `blocks.json` in the data dir, e.g. `~/.local/share/plurk-cli`, holding
+/// the ids of the blocked users.
+fn mirror_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("plurk-cli")
+        .join("blocks.json")
+}
+
+/// Ids of the blocked users, as last listed or changed by the cli.
+pub fn load_mirror() -> HashSet<u64> {
+    let path = mirror_path();
+    match fs::read_to_string(&path) {
+        Ok(s) => serde_json::from_str(&s).unwrap_or_else(|e| {
+            eprintln!("Warning: {}: {}", path.display(), e);
+            HashSet::new()
+        }),
+        Err(_) => HashSet::new(),
+    }
+}
+
+fn save_mirror(ids: &HashSet<u64>) -> Result<(), PlurkError> {
+    let path = mirror_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| PlurkError::IOError(e.to_string()))?;
+    }
+    let mut ids: Vec<&u64> = ids.iter().collect();
+    ids.sort();
+    let s = serde_json::to_string(&ids).map_err(|e| PlurkError::ParseError(e.to_string()))?;
+    fs::write(&path, s).map_err(|e| PlurkError::IOError(format!("{}: {}", path.display(), e)))
+}
+
+/// Print the blocked users. Only a listing that reaches the end refreshes
+/// the local mirror, one cut by `limit` can't tell who was unblocked.
+pub async fn print_blocks(
+    plurk: &Plurk,
+    limit: Option<u64>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let mut ids = HashSet::new();
+    let mut offset = 0;
+    let complete = loop {
+        if limit.is_some_and(|n| offset >= n) {
+            break false;
+        }
+        let page = plurk.get_blocks(offset).await?;
+        if page.users.is_empty() {
+            break true;
+        }
+        let wanted = limit.map_or(page.users.len(), |n| (n - offset) as usize);
+        for u in page.users.iter().take(wanted) {
+            println!("{}", user_line(u, opts));
+        }
+        ids.extend(page.users.iter().map(|u| u.id));
+        offset += page.users.len() as u64;
+        if offset >= page.total {
+            break true;
+        }
+    };
+    if offset == 0 {
+        eprintln!("No blocked users");
+    }
+    if complete {
+        save_mirror(&ids)?;
+    }
+    Ok(())
+}
+
+/// Block users, or with `unblock` unblock them.
+pub async fn update(plurk: &Plurk, nicks: &[String], unblock: bool) -> Result<(), PlurkError> {
+    let (api, done) = if unblock {
+        ("unblock", "Unblocked")
+    } else {
+        ("block", "Blocked")
+    };
+    let mut ids = load_mirror();
+    for nick in nicks {
+        let user = plurk.find_user(nick).await?;
+        plurk
+            .blocks(api, &[("user_id", user.id.to_string())])
+            .await?;
+        if unblock {
+            ids.remove(&user.id);
+        } else {
+            ids.insert(user.id);
+        }
+        // Saved each time, so a later failure keeps the ones done.
+        save_mirror(&ids)?;
+        println!("{} {} @{}", done, user.display_name, user.nick_name);
+    }
+    Ok(())
+}
@@ -0,0 +1,157 @@
+// cliques.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Cliques, the named groups of friends a plurk can be limited to.
+
+use crate::app::user_line;
+use crate::error::PlurkError;
+use crate::plurk::Plurk;
+use crate::render::RenderOptions;
+
+pub async fn print_cliques(plurk: &Plurk) -> Result<(), PlurkError> {
+    let cliques = plurk.get_cliques().await?;
+    if cliques.is_empty() {
+        eprintln!("No cliques, create one with `plurk cliques create <name>`");
+    }
+    for name in cliques {
+        println!("{}", name);
+    }
+    Ok(())
+}
+
+pub async fn print_clique(
+    plurk: &Plurk,
+    name: &str,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let members = plurk.get_clique(name).await?;
+    if members.is_empty() {
+        eprintln!("No members in {}", name);
+    }
+    for u in &members {
+        println!("{}", user_line(u, opts));
+    }
+    Ok(())
+}
+
+pub async fn create(plurk: &Plurk, name: &str) -> Result<(), PlurkError> {
+    plurk
+        .cliques("createClique", &[("clique_name", name.to_string())])
+        .await?;
+    println!("Created {}", name);
+    Ok(())
+}
+
+pub async fn rename(plurk: &Plurk, name: &str, new_name: &str) -> Result<(), PlurkError> {
+    plurk
+        .cliques(
+            "renameClique",
+            &[
+                ("clique_name", name.to_string()),
+                ("new_name", new_name.to_string()),
+            ],
+        )
+        .await?;
+    println!("Renamed {} to {}", name, new_name);
+    Ok(())
+}
+
+/// Add users to a clique, or with `remove` take them out.
+pub async fn update(
+    plurk: &Plurk,
+    name: &str,
+    nicks: &[String],
+    remove: bool,
+) -> Result<(), PlurkError> {
+    let (api, done) = if remove {
+        ("remove", "Removed from")
+    } else {
+        ("add", "Added to")
+    };
+    for nick in nicks {
+        let user = plurk.find_user(nick).await?;
+        plurk
+            .cliques(
+                api,
+                &[
+                    ("clique_name", name.to_string()),
+                    ("user_id", user.id.to_string()),
+                ],
+            )
+            .await?;
+        println!(
+            "{} {}: {} @{}",
+            done, name, user.display_name, user.nick_name
+        );
+    }
+    Ok(())
+}
+
+/// The `limited_to` of a new plurk: the members of `clique` and the users
+/// in `to`, or `[0]` for friends only, which can't be combined with them.
+/// `None` posts publicly.
+pub async fn limited_to(
+    plurk: &Plurk,
+    clique: Option<&str>,
+    to: &[String],
+    friends_only: bool,
+) -> Result<Option<Vec<u64>>, PlurkError> {
+    if friends_only {
+        if clique.is_some() || !to.is_empty() {
+            return Err(PlurkError::UsageError(String::from(
+                "--friends-only can't be combined with --to-clique or --to",
+            )));
+        }
+        return Ok(Some(vec![0]));
+    }
+    let mut ids = Vec::new();
+    if let Some(name) = clique {
+        let members = plurk.get_clique(name).await?;
+        if members.is_empty() {
+            return Err(PlurkError::UsageError(format!(
+                "clique {} has no members",
+                name
+            )));
+        }
+        ids.extend(members.iter().map(|u| u.id));
+    }
+    for nick in to {
+        ids.push(plurk.find_user(nick).await?.id);
+    }
+    if clique.is_none() && to.is_empty() {
+        return Ok(None);
+    }
+    ids.sort_unstable();
+    ids.dedup();
+    Ok(Some(ids))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn offline() -> Plurk {
+        Plurk::new(String::from("key"), String::from("secret"), None, None)
+    }
+
+    #[tokio::test]
+    async fn friends_only_is_zero() {
+        let ids = limited_to(&offline(), None, &[], true).await.unwrap();
+        assert_eq!(ids, Some(vec![0]));
+    }
+
+    #[tokio::test]
+    async fn friends_only_excludes_other_limits() {
+        let to = [String::from("nick")];
+        let err = limited_to(&offline(), None, &to, true).await;
+        assert!(matches!(err, Err(PlurkError::UsageError(_))));
+        let err = limited_to(&offline(), Some("clique"), &[], true).await;
+        assert!(matches!(err, Err(PlurkError::UsageError(_))));
+    }
+
+    #[tokio::test]
+    async fn public_without_limits() {
+        let ids = limited_to(&offline(), None, &[], false).await.unwrap();
+        assert_eq!(ids, None);
+    }
+}
@@ -3,10 +3,10 @@
 // Distributed under terms of the MIT license.
 
 use crate::error::PlurkError;
-use crate::plurk::{Plurk, PlurkData, PlurkUser};
+use crate::images::preview;
+use crate::plurk::{Plurk, PlurkData, PlurkResponse, PlurkUser};
+use crate::render::RenderOptions;
 use crate::utils::*;
-use chrono::{self, DateTime, FixedOffset};
-use colored::Colorize;
 use regex::Regex;
 use reqwest::Url;
 use serde::Deserialize;
@@ -16,6 +16,13 @@ use std::fmt;
 use std::time::Duration;
 
 const COMET_KNOCK: &str = "https://www.plurk.com/_comet/generic";
+/// Knock on the channel every this many polls, so it stays alive.
+const KNOCK_EVERY: u8 = 10;
+/// Give up after this many failures in a row.
+const RETRIES: u32 = 10;
+/// Wait before retrying after a failure, doubled on each one up to a minute.
+const BACKOFF: Duration = Duration::from_secs(2);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
 
 #[derive(Clone, Debug)]
 pub struct PlurkComet {
@@ -44,8 +51,8 @@ pub enum CometContentUnit {
     Response {
         plurk_id: u64,
         #[serde(rename = "plurk")]
-        plurk_data: PlurkData,
-        response: CometResponse,
+        plurk_data: Box<PlurkData>,
+        response: PlurkResponse,
         response_count: u64,
         user: HashMap<String, PlurkUser>,
     },
@@ -55,27 +62,43 @@ pub enum CometContentUnit {
     Notification { counts: CometNotiCount },
 }
 
-#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
-pub struct CometResponse {
-    content: String,
-    content_raw: String,
-    editability: u8,
-    id: u64,
-    lang: String,
-    last_edited: Option<WrappedDT>,
-    plurk_id: u64,
-    #[serde(deserialize_with = "from_rfc2822")]
-    pub posted: DateTime<FixedOffset>,
-    qualifier: String,
-    user_id: u64,
+pub struct CometNotiCount {
+    pub noti: u32,
+    pub req: u32,
 }
 
-#[allow(dead_code)]
-#[derive(Deserialize, Debug)]
-pub struct CometNotiCount {
-    noti: u32,
-    req: u32,
+impl CometContentUnit {
+    /// Whether the plurk or response of the event is by a blocked user.
+    pub fn hidden(&self, opts: &RenderOptions) -> bool {
+        match self {
+            CometContentUnit::Response {
+                plurk_data,
+                response,
+                ..
+            } => opts.hides(plurk_data) || opts.blocked.contains(&response.user_id),
+            CometContentUnit::Plurk(p) => opts.hides(p),
+            CometContentUnit::Notification { .. } => false,
+        }
+    }
+}
+
+impl CometNotiCount {
+    /// The unread counts in words, pointing at `plurk alerts`.
+    pub fn summary(&self) -> String {
+        let count = |n: u32, what: &str| match n {
+            1 => format!("1 {}", what),
+            n => format!("{} {}s", n, what),
+        };
+        if self.noti == 0 && self.req == 0 {
+            return String::from("No unread alerts");
+        }
+        format!(
+            "{} and {}, run `plurk alerts` to see them",
+            count(self.noti, "new alert"),
+            count(self.req, "friend request")
+        )
+    }
 }
 
 #[derive(Deserialize, Debug)]
@@ -99,7 +122,7 @@ impl PlurkComet {
         let url = Url::parse(comet_url).map_err(|e| PlurkError::ParseError(e.to_string()))?;
         let query = match url.query() {
             Some(q) => q,
-            None => return Err(PlurkError::InvalidUrl(comet_url.to_string()).into()),
+            None => return Err(PlurkError::InvalidUrl(comet_url.to_string())),
         };
 
         let comet_datas: CometDatas =
@@ -132,9 +155,9 @@ impl PlurkComet {
             .timeout(Duration::from_secs(120))
             .send()
             .await
-            .map_err(|e| PlurkError::ReqwestError(e))?;
+            .map_err(PlurkError::ReqwestError)?;
 
-        let text = res.text().await.map_err(|e| PlurkError::ReqwestError(e))?;
+        let text = res.text().await.map_err(PlurkError::ReqwestError)?;
 
         let res = PlurkComet::query(text.as_str())?;
         self.offset = res.new_offset;
@@ -147,14 +170,14 @@ impl PlurkComet {
             .map_err(|e| PlurkError::InvalidCometData(e.to_string()))?;
         let mat = match re.captures(comet_callback) {
             Some(m) => m,
-            None => return Err(PlurkError::InvalidCometData(comet_callback.to_string()).into()),
+            None => return Err(PlurkError::InvalidCometData(comet_callback.to_string())),
         };
         serde_json::from_str(&mat[1])
             .map_err(|e| PlurkError::InvalidCometData(format!("{}\n{}", e, comet_callback)))
     }
 
     pub async fn knock(&self) -> Result<(), PlurkError> {
-        let url = Url::parse_with_params(&COMET_KNOCK, &[("channel", &self.channel)])
+        let url = Url::parse_with_params(COMET_KNOCK, &[("channel", &self.channel)])
             .map_err(|_| PlurkError::UrlError)?;
 
         let client = reqwest::Client::new();
@@ -163,15 +186,15 @@ impl PlurkComet {
             .get(url)
             .send()
             .await
-            .map_err(|e| PlurkError::ReqwestError(e))?;
+            .map_err(PlurkError::ReqwestError)?;
         Ok(())
     }
 
-    pub async fn print_comet(plurk: &Plurk, comet: CometContentUnit) -> Result<(), PlurkError> {
-        #[derive(Deserialize)]
-        struct ObjGetPublicProfile {
-            user_info: PlurkUser,
-        }
+    pub async fn print_comet(
+        plurk: &Plurk,
+        comet: CometContentUnit,
+        opts: &RenderOptions,
+    ) -> Result<(), PlurkError> {
         match comet {
             CometContentUnit::Response {
                 plurk_id,
@@ -180,19 +203,7 @@ impl PlurkComet {
                 response_count: _,
                 user,
             } => {
-                let resp = plurk
-                    .request_query(
-                        "/APP/Profile/getPublicProfile",
-                        &[("user_id", plurk_data.owner_id)],
-                    )
-                    .await?;
-
-                let plurk_owner = resp
-                    .json::<ObjGetPublicProfile>()
-                    .await
-                    .map_err(|e| PlurkError::ParseError(e.to_string()))?;
-
-                let display_name = plurk_owner.user_info.display_name;
+                let owner = plurk.get_public_profile(plurk_data.owner_id).await?;
 
                 let response_display_name = &user
                     .get(&response.user_id.to_string())
@@ -203,65 +214,103 @@ impl PlurkComet {
                     base36_encode(plurk_id)
                 );
                 println!(
-                    "{} {} {}",
-                    plurk_data
-                        .posted
-                        .with_timezone(&chrono::Local)
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .yellow(),
-                    display_name.bold().bright_blue(),
-                    plurk_data.qualifier.black().on_bright_white()
+                    "{}",
+                    opts.header(
+                        &plurk_data,
+                        &owner.display_name,
+                        owner.name_color.as_deref()
+                    )
                 );
-                println!("{}", plurk_data.content_raw);
-                println!(" -------");
                 println!(
-                    "{} {} {} {}",
-                    response
-                        .posted
-                        .with_timezone(&chrono::Local)
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .yellow(),
-                    response_display_name.bold().bright_magenta(),
-                    response.qualifier.black().on_bright_white(),
-                    response.content_raw
+                    "{}",
+                    opts.body(&opts.content(&plurk_data.content_raw, &plurk_data.content))
                 );
+                preview(opts, &plurk_data.content).await;
+                println!("{}", opts.rule());
+                println!("{}", opts.response(&response, response_display_name, 0));
+                preview(opts, &response.content).await;
             }
             CometContentUnit::Plurk(p) => {
-                let resp = plurk
-                    .request_query("/APP/Profile/getPublicProfile", &[("user_id", p.owner_id)])
-                    .await?;
-
-                let plurk_owner = resp
-                    .json::<ObjGetPublicProfile>()
-                    .await
-                    .map_err(|e| PlurkError::ParseError(e.to_string()))?;
-
-                let display_name = plurk_owner.user_info.display_name;
+                let owner = plurk.get_public_profile(p.owner_id).await?;
                 println!(
                     "New plurk ==> https://www.plurk.com/p/{}",
                     base36_encode(p.plurk_id)
                 );
                 println!(
-                    "{} {} {}",
-                    p.posted
-                        .with_timezone(&chrono::Local)
-                        .format("%Y-%m-%d %H:%M:%S")
-                        .to_string()
-                        .bright_yellow(),
-                    display_name.bold().bright_blue(),
-                    p.qualifier.black().on_bright_white()
+                    "{}",
+                    opts.header(&p, &owner.display_name, owner.name_color.as_deref())
                 );
-                println!("{}", p.content_raw);
+                println!("{}", opts.body(&opts.content(&p.content_raw, &p.content)));
+                preview(opts, &p.content).await;
             }
             CometContentUnit::Notification { counts } => {
-                println!("Notification: {:?}", counts);
+                println!("{}", counts.summary());
             }
         };
         Ok(())
     }
 }
+/// Events of the comet channel of a user, reconnecting when it fails.
+pub struct CometFeed {
+    plurk: Plurk,
+    comet: Option<PlurkComet>,
+    polls: u8,
+    failures: u32,
+}
+
+impl CometFeed {
+    pub fn new(plurk: Plurk) -> Self {
+        Self {
+            plurk,
+            comet: None,
+            polls: 0,
+            failures: 0,
+        }
+    }
+
+    /// The next events, often none when the long poll times out. Failures
+    /// are retried on a new channel after a growing delay, the error is only
+    /// returned after `RETRIES` of them in a row. Cancelling it between
+    /// events loses nothing, the channel offset is kept.
+    pub async fn next(&mut self) -> Result<Vec<CometContentUnit>, PlurkError> {
+        loop {
+            match self.poll().await {
+                Ok(datas) => {
+                    self.failures = 0;
+                    return Ok(datas);
+                }
+                Err(e) => {
+                    self.failures += 1;
+                    if self.failures >= RETRIES {
+                        return Err(e);
+                    }
+                    self.comet = None;
+                    let backoff = BACKOFF.saturating_mul(2u32.saturating_pow(self.failures - 1));
+                    tokio::time::sleep(backoff.min(MAX_BACKOFF)).await;
+                }
+            }
+        }
+    }
+
+    async fn poll(&mut self) -> Result<Vec<CometContentUnit>, PlurkError> {
+        let comet = match &mut self.comet {
+            Some(comet) => comet,
+            None => {
+                let comet = PlurkComet::from_plurk(self.plurk.clone()).await?;
+                self.polls = 0;
+                self.comet.insert(comet)
+            }
+        };
+        if self.polls >= KNOCK_EVERY {
+            comet.knock().await?;
+            self.polls = 0;
+        } else {
+            self.polls += 1;
+        }
+        Ok(comet.poll_once_mut().await?.unwrap_or_default())
+    }
+}
+
 impl fmt::Display for PlurkComet {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -0,0 +1,65 @@
+// config.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Preferences read from `config.toml` in the config dir.
+
+use crate::app::config_dir;
+use crate::error::PlurkError;
+use crate::theme::Theme;
+use chrono::format::{Item, StrftimeItems};
+use serde::Deserialize;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+
+#[derive(Deserialize, Debug, Clone)]
+#[serde(default)]
+pub struct Config {
+    /// strftime format of absolute timestamps, e.g. `%d/%m %H:%M`
+    pub date_format: Option<String>,
+    /// Send long outputs through `$PAGER`.
+    pub pager: bool,
+    /// Keep the plurks seen in the local archive.
+    pub archive: bool,
+    pub theme: Theme,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            date_format: None,
+            pager: true,
+            archive: true,
+            theme: Theme::default(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        config_dir().join("config.toml")
+    }
+
+    /// Load the config file, a missing file gives the defaults.
+    pub fn load() -> Result<Self, PlurkError> {
+        let path = Self::path();
+        let s = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(PlurkError::IOError(e.to_string())),
+        };
+        let config: Self = toml::from_str(&s)
+            .map_err(|e| PlurkError::ParseError(format!("{}: {}", path.display(), e)))?;
+        // chrono only reports a bad format when printing, by panicking.
+        if let Some(format) = &config.date_format {
+            if StrftimeItems::new(format).any(|item| item == Item::Error) {
+                return Err(PlurkError::ParseError(format!(
+                    "{}: invalid date_format `{}`",
+                    path.display(),
+                    format
+                )));
+            }
+        }
+        Ok(config)
+    }
+}
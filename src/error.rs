@@ -14,7 +14,13 @@ pub enum PlurkError {
     OauthError(reqwest_oauth1::Error),
     ReqwestError(reqwest::Error),
     UrlError,
-    StdError(Box<dyn Error>),
+    SecretError(String),
+    NotInteractive(String),
+    Timeout(String),
+    ApiError(String),
+    DatabaseError(String),
+    UsageError(String),
+    StdError(Box<dyn Error + Send + Sync>),
 }
 
 impl fmt::Display for PlurkError {
@@ -27,6 +33,12 @@ impl fmt::Display for PlurkError {
             Self::OauthError(e) => write!(f, "oauth1 error: {}", e),
             Self::ReqwestError(e) => write!(f, "reqwest error: {}", e),
             Self::UrlError => write!(f, "url error"),
+            Self::SecretError(e) => write!(f, "secret backend error: {}", e),
+            Self::NotInteractive(e) => write!(f, "not interactive: {}", e),
+            Self::Timeout(e) => write!(f, "timed out waiting for {}", e),
+            Self::ApiError(e) => write!(f, "plurk api error: {}", e),
+            Self::DatabaseError(e) => write!(f, "archive error: {}", e),
+            Self::UsageError(e) => write!(f, "usage error: {}", e),
             Self::StdError(e) => write!(f, "std error: {}", e),
         }
     }
@@ -0,0 +1,390 @@
+// export.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Self-contained backup of an account: JSONL per entity, media and a manifest.
+
+use crate::api::timeline_offset;
+use crate::archive::Archive;
+use crate::error::PlurkError;
+use crate::images::{fetch, image_urls};
+use crate::plurk::{Plurk, PlurkData, PlurkResponse, PlurkUser};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashSet;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+pub const PLURKS_FILE: &str = "plurks.jsonl";
+pub const RESPONSES_FILE: &str = "responses.jsonl";
+pub const USERS_FILE: &str = "users.jsonl";
+pub const MEDIA_FILE: &str = "media.jsonl";
+pub const MEDIA_DIR: &str = "media";
+pub const MANIFEST_FILE: &str = "manifest.json";
+const STATE_FILE: &str = "export-state.json";
+/// Bumped when the layout of the export changes.
+const FORMAT: u32 = 1;
+/// Plurks per page of `/APP/Timeline/getPlurks`.
+const PAGE: u64 = 30;
+
+/// Progress of an export, so an interrupted one continues where it was.
+#[derive(Serialize, Deserialize, Default)]
+struct ExportState {
+    /// `offset` of the next timeline page.
+    offset: Option<String>,
+    complete: bool,
+    /// When adding the plurks posted since a finished export, the unix time
+    /// of its newest plurk. The walk stops past it.
+    #[serde(default)]
+    stop_at: Option<i64>,
+}
+
+/// Lines of a JSONL file that parse, dropping a line cut by an interruption.
+pub fn read_jsonl(path: &Path) -> Result<Vec<Value>, PlurkError> {
+    let file = match File::open(path) {
+        Ok(file) => file,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(PlurkError::IOError(format!("{}: {}", path.display(), e))),
+    };
+    let mut values = Vec::new();
+    for line in BufReader::new(file).lines() {
+        let line = line.map_err(|e| PlurkError::IOError(e.to_string()))?;
+        if let Ok(v) = serde_json::from_str(&line) {
+            values.push(v);
+        }
+    }
+    Ok(values)
+}
+
+fn id_of(v: &Value, key: &str) -> Option<u64> {
+    v.get(key).and_then(Value::as_u64)
+}
+
+/// An append-only JSONL file.
+struct Jsonl {
+    file: File,
+}
+
+impl Jsonl {
+    /// Open for appending, first rewriting it with only the `kept` lines.
+    fn open(path: &Path, kept: &[Value]) -> Result<Self, PlurkError> {
+        let io_err = |e: std::io::Error| PlurkError::IOError(format!("{}: {}", path.display(), e));
+        let mut file = File::create(path).map_err(io_err)?;
+        for v in kept {
+            writeln!(file, "{}", v).map_err(io_err)?;
+        }
+        let file = OpenOptions::new().append(true).open(path).map_err(io_err)?;
+        Ok(Jsonl { file })
+    }
+
+    fn append<T: Serialize>(&mut self, v: &T) -> Result<(), PlurkError> {
+        let line = serde_json::to_string(v).map_err(|e| PlurkError::ParseError(e.to_string()))?;
+        writeln!(self.file, "{}", line).map_err(|e| PlurkError::IOError(e.to_string()))
+    }
+}
+
+/// Write a file through a temporary one renamed over it, so an interruption
+/// leaves either the old or the new content.
+fn write_atomic(path: &Path, data: &str) -> Result<(), PlurkError> {
+    let io_err = |e: std::io::Error| PlurkError::IOError(format!("{}: {}", path.display(), e));
+    let tmp = path.with_extension("tmp");
+    fs::write(&tmp, data).map_err(io_err)?;
+    fs::rename(&tmp, path).map_err(io_err)
+}
+
+struct Export {
+    dir: PathBuf,
+    plurks: Jsonl,
+    responses: Jsonl,
+    users: Jsonl,
+    media: Jsonl,
+    plurk_ids: HashSet<u64>,
+    /// Unix time of the newest plurk exported.
+    newest: Option<i64>,
+    user_ids: HashSet<u64>,
+    media_urls: HashSet<String>,
+    response_count: u64,
+    images: bool,
+}
+
+impl Export {
+    /// Open the export in `dir`, keeping what a previous run finished.
+    fn open(dir: &Path, images: bool) -> Result<Self, PlurkError> {
+        fs::create_dir_all(dir.join(MEDIA_DIR)).map_err(|e| PlurkError::IOError(e.to_string()))?;
+        let plurks = read_jsonl(&dir.join(PLURKS_FILE))?;
+        let plurk_ids: HashSet<u64> = plurks.iter().filter_map(|v| id_of(v, "plurk_id")).collect();
+        let newest = plurks
+            .iter()
+            .filter_map(|v| serde_json::from_value::<PlurkData>(v.clone()).ok())
+            .map(|p| p.posted.timestamp())
+            .max();
+        // Responses of a plurk that wasn't finished are fetched again.
+        let responses: Vec<Value> = read_jsonl(&dir.join(RESPONSES_FILE))?
+            .into_iter()
+            .filter(|v| id_of(v, "plurk_id").is_some_and(|id| plurk_ids.contains(&id)))
+            .collect();
+        let users = read_jsonl(&dir.join(USERS_FILE))?;
+        let media = read_jsonl(&dir.join(MEDIA_FILE))?;
+
+        Ok(Export {
+            plurks: Jsonl::open(&dir.join(PLURKS_FILE), &plurks)?,
+            responses: Jsonl::open(&dir.join(RESPONSES_FILE), &responses)?,
+            users: Jsonl::open(&dir.join(USERS_FILE), &users)?,
+            media: Jsonl::open(&dir.join(MEDIA_FILE), &media)?,
+            user_ids: users.iter().filter_map(|v| id_of(v, "id")).collect(),
+            media_urls: media
+                .iter()
+                .filter_map(|v| v.get("url").and_then(Value::as_str).map(String::from))
+                .collect(),
+            response_count: responses.len() as u64,
+            plurk_ids,
+            newest,
+            dir: dir.to_path_buf(),
+            images,
+        })
+    }
+
+    fn state(&self) -> ExportState {
+        fs::read_to_string(self.dir.join(STATE_FILE))
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    fn save_state(&self, state: &ExportState) -> Result<(), PlurkError> {
+        let s = serde_json::to_string_pretty(state)
+            .map_err(|e| PlurkError::ParseError(e.to_string()))?;
+        write_atomic(&self.dir.join(STATE_FILE), &s)
+    }
+
+    fn add_users<'a>(
+        &mut self,
+        users: impl IntoIterator<Item = &'a PlurkUser>,
+    ) -> Result<(), PlurkError> {
+        for u in users {
+            if self.user_ids.insert(u.id) {
+                self.users.append(u)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Download the images of `content` into the media folder.
+    async fn add_media(&mut self, content: &str) -> Result<(), PlurkError> {
+        if !self.images {
+            return Ok(());
+        }
+        for url in image_urls(content) {
+            if self.media_urls.contains(&url) {
+                continue;
+            }
+            let data = match fetch(&url).await {
+                Ok(data) => data,
+                Err(e) => {
+                    eprintln!("Warning: {} ({})", url, e);
+                    continue;
+                }
+            };
+            let sha256 = format!("{:x}", Sha256::digest(&data));
+            let ext = Path::new(url.split(['?', '#']).next().unwrap_or_default())
+                .extension()
+                .and_then(|e| e.to_str())
+                .filter(|e| e.len() <= 5)
+                .unwrap_or("bin")
+                .to_ascii_lowercase();
+            let file = format!("{}/{}.{}", MEDIA_DIR, sha256, ext);
+            fs::write(self.dir.join(&file), &data)
+                .map_err(|e| PlurkError::IOError(e.to_string()))?;
+            self.media
+                .append(&json!({ "url": url, "file": file, "sha256": sha256 }))?;
+            self.media_urls.insert(url);
+        }
+        Ok(())
+    }
+
+    /// Export one plurk with all its responses and images. The plurk line is
+    /// written last, marking it as done.
+    async fn add_plurk(&mut self, plurk: &Plurk, p: &PlurkData) -> Result<(), PlurkError> {
+        if self.plurk_ids.contains(&p.plurk_id) {
+            return Ok(());
+        }
+        self.add_media(&p.content).await?;
+
+        let body = plurk.get_all_responses(p.plurk_id, 0).await?;
+        self.add_users(body.friends.values())?;
+        for r in &body.responses {
+            self.responses.append(r)?;
+            self.add_media(&r.content).await?;
+        }
+        self.response_count += body.responses.len() as u64;
+
+        self.plurks.append(p)?;
+        self.plurk_ids.insert(p.plurk_id);
+        self.newest = self.newest.max(Some(p.posted.timestamp()));
+        Ok(())
+    }
+
+    fn write_manifest(&self, me: &PlurkUser) -> Result<(), PlurkError> {
+        let mut files = Vec::new();
+        let mut paths = vec![
+            PathBuf::from(PLURKS_FILE),
+            PathBuf::from(RESPONSES_FILE),
+            PathBuf::from(USERS_FILE),
+            PathBuf::from(MEDIA_FILE),
+        ];
+        let mut media: Vec<PathBuf> = fs::read_dir(self.dir.join(MEDIA_DIR))
+            .map_err(|e| PlurkError::IOError(e.to_string()))?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| Path::new(MEDIA_DIR).join(entry.file_name()))
+            .collect();
+        media.sort();
+        paths.extend(media);
+        for path in paths {
+            let data =
+                fs::read(self.dir.join(&path)).map_err(|e| PlurkError::IOError(e.to_string()))?;
+            files.push(json!({
+                "path": path.to_string_lossy().replace('\\', "/"),
+                "bytes": data.len(),
+                "sha256": format!("{:x}", Sha256::digest(&data)),
+            }));
+        }
+        let manifest = json!({
+            "format": FORMAT,
+            "generator": concat!("plurk-cli ", env!("CARGO_PKG_VERSION")),
+            "exported_at": chrono::Utc::now().to_rfc3339(),
+            "user": { "id": me.id, "nick_name": me.nick_name, "display_name": me.display_name },
+            "counts": {
+                "plurks": self.plurk_ids.len(),
+                "responses": self.response_count,
+                "users": self.user_ids.len(),
+                "media": self.media_urls.len(),
+            },
+            "files": files,
+        });
+        let s = serde_json::to_string_pretty(&manifest)
+            .map_err(|e| PlurkError::ParseError(e.to_string()))?;
+        write_atomic(&self.dir.join(MANIFEST_FILE), &s)
+    }
+}
+
+/// Export the plurks of the account, or with `all` the whole timeline, to
+/// `dir`. Running it again resumes an interrupted export, or adds the plurks
+/// posted since a finished one.
+pub async fn export(plurk: &Plurk, dir: &Path, all: bool, images: bool) -> Result<(), PlurkError> {
+    let me = plurk.get_me().await?;
+    let mut export = Export::open(dir, images)?;
+    let state = export.state();
+    // A finished export walks from the newest plurk again, back to the ones
+    // it already has.
+    let (mut offset, stop_at) = if state.complete {
+        (None, export.newest)
+    } else {
+        (state.offset, state.stop_at)
+    };
+    export.save_state(&ExportState {
+        offset: offset.clone(),
+        complete: false,
+        stop_at,
+    })?;
+    let filter = if all { None } else { Some("only_user") };
+    let mut seen = HashSet::new();
+
+    loop {
+        let page = plurk.get_timeline(offset.as_deref(), PAGE, filter).await?;
+        let plurks = page.plurks.unwrap_or_default();
+        let Some(last) = plurks.last() else {
+            break;
+        };
+        export.add_users(page.plurk_users.iter().flat_map(|users| users.values()))?;
+        let mut fresh = false;
+        for p in &plurks {
+            fresh |= seen.insert(p.plurk_id);
+            export.add_plurk(plurk, p).await?;
+        }
+
+        // A page all seen is a single second, step past it.
+        offset = Some(timeline_offset(&last.posted, fresh));
+        export.save_state(&ExportState {
+            offset: offset.clone(),
+            complete: false,
+            stop_at,
+        })?;
+        eprintln!(
+            "Exported {} plurks, {} responses (back to {})",
+            export.plurk_ids.len(),
+            export.response_count,
+            last.posted.format("%Y-%m-%d")
+        );
+        // The rest was exported by the previous run.
+        if stop_at.is_some_and(|t| last.posted.timestamp() < t) {
+            break;
+        }
+    }
+
+    export.write_manifest(&me)?;
+    export.save_state(&ExportState {
+        offset: None,
+        complete: true,
+        stop_at: None,
+    })?;
+    println!(
+        "Exported {} plurks, {} responses, {} users and {} images to {}",
+        export.plurk_ids.len(),
+        export.response_count,
+        export.user_ids.len(),
+        export.media_urls.len(),
+        dir.display()
+    );
+    Ok(())
+}
+
+/// Load an export into the archive. Entries already archived are updated.
+pub fn import(archive: &Archive, dir: &Path) -> Result<(), PlurkError> {
+    let parse = |e: serde_json::Error| PlurkError::ParseError(e.to_string());
+    if !dir.join(PLURKS_FILE).exists() {
+        return Err(PlurkError::IOError(format!(
+            "{}: not an export, {} is missing",
+            dir.display(),
+            PLURKS_FILE
+        )));
+    }
+    if let Ok(manifest) = fs::read_to_string(dir.join(MANIFEST_FILE)) {
+        let manifest: Value = serde_json::from_str(&manifest).map_err(parse)?;
+        if manifest["format"]
+            .as_u64()
+            .is_some_and(|f| f > u64::from(FORMAT))
+        {
+            return Err(PlurkError::ParseError(format!(
+                "{}: export format {} is newer than this plurk-cli",
+                dir.display(),
+                manifest["format"]
+            )));
+        }
+    }
+
+    let plurks: Vec<PlurkData> = read_jsonl(&dir.join(PLURKS_FILE))?
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<_, _>>()
+        .map_err(parse)?;
+    let responses: Vec<PlurkResponse> = read_jsonl(&dir.join(RESPONSES_FILE))?
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<_, _>>()
+        .map_err(parse)?;
+    let users: Vec<PlurkUser> = read_jsonl(&dir.join(USERS_FILE))?
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<_, _>>()
+        .map_err(parse)?;
+    archive.save(&plurks, &responses, &users)?;
+    println!(
+        "Imported {} plurks, {} responses and {} users from {}",
+        plurks.len(),
+        responses.len(),
+        users.len(),
+        dir.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,105 @@
+// friends.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Friends, fans and followed users.
+
+use crate::app::user_line;
+use crate::archive::{record, Archive};
+use crate::error::PlurkError;
+use crate::plurk::Plurk;
+use crate::render::RenderOptions;
+
+/// Users per page of the `*ByOffset` APIs, the most they return.
+const PAGE: u64 = 100;
+
+#[derive(Clone, Copy, Debug)]
+pub enum Relation {
+    Friends,
+    Fans,
+    Following,
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum Action {
+    Befriend,
+    Unfriend,
+    Fan,
+    Follow,
+    Unfollow,
+}
+
+/// Print the friends or fans of `user`, the user owning the token if not
+/// given, or the users followed. Plurk only lists your own followed users.
+pub async fn print_relations(
+    plurk: &Plurk,
+    relation: Relation,
+    user: Option<&str>,
+    limit: Option<u64>,
+    archive: Option<&Archive>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let (api, user_id) = match relation {
+        Relation::Friends | Relation::Fans => {
+            let user = match user {
+                Some(user) => plurk.find_user(user).await?,
+                None => plurk.get_me().await?,
+            };
+            let api = match relation {
+                Relation::Friends => "getFriendsByOffset",
+                _ => "getFansByOffset",
+            };
+            (api, Some(user.id))
+        }
+        Relation::Following => ("getFollowingByOffset", None),
+    };
+
+    let mut offset = 0;
+    while limit.is_none_or(|n| offset < n) {
+        let users = plurk.get_relations(api, user_id, offset, PAGE).await?;
+        record(archive, [], [], &users);
+        let wanted = limit.map_or(users.len(), |n| (n - offset) as usize);
+        for u in users.iter().take(wanted) {
+            println!("{}", user_line(u, opts));
+        }
+        offset += users.len() as u64;
+        if (users.len() as u64) < PAGE {
+            break;
+        }
+    }
+    if offset == 0 {
+        eprintln!("No users found");
+    }
+    Ok(())
+}
+
+/// Change the relation with `nick`.
+pub async fn act(plurk: &Plurk, action: Action, nick: &str) -> Result<(), PlurkError> {
+    let user = plurk.find_user(nick).await?;
+    let id = user.id.to_string();
+    let (api, params, done) = match action {
+        Action::Befriend => (
+            "becomeFriend",
+            vec![("friend_id", id)],
+            "Sent a friend request to",
+        ),
+        Action::Unfriend => (
+            "removeAsFriend",
+            vec![("friend_id", id)],
+            "Removed from your friends:",
+        ),
+        Action::Fan => ("becomeFan", vec![("fan_id", id)], "Became a fan of"),
+        Action::Follow => (
+            "setFollowing",
+            vec![("user_id", id), ("follow", String::from("true"))],
+            "Following",
+        ),
+        Action::Unfollow => (
+            "setFollowing",
+            vec![("user_id", id), ("follow", String::from("false"))],
+            "Stopped following",
+        ),
+    };
+    plurk.friends_fans(api, &params).await?;
+    println!("{} {} @{}", done, user.display_name, user.nick_name);
+    Ok(())
+}
@@ -0,0 +1,304 @@
+// images.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Inline previews of the images attached to plurks.
+
+use crate::error::PlurkError;
+use crate::render::{parse_html, RenderOptions};
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+use clap::ValueEnum;
+use image::imageops::FilterType;
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use sha2::{Digest, Sha256};
+use std::env;
+use std::fs;
+use std::io::Cursor;
+use std::path::PathBuf;
+
+const IMAGE_HOST: &str = "images.plurk.com";
+/// Images larger than this are not downloaded.
+const MAX_IMAGE_BYTES: usize = 8 * 1024 * 1024;
+const KITTY_CHUNK: usize = 4096;
+
+/// How images attached to plurks are previewed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ImageMode {
+    /// Don't download images
+    Off,
+    /// Pick the protocol supported by the terminal
+    Auto,
+    /// Kitty graphics protocol
+    Kitty,
+    /// iTerm2 inline images
+    Iterm,
+    /// Sixel graphics
+    Sixel,
+    /// Half block characters in true color
+    Blocks,
+}
+
+impl ImageMode {
+    fn detect() -> Self {
+        let var = |name| env::var(name).unwrap_or_default();
+        if env::var("KITTY_WINDOW_ID").is_ok() || var("TERM") == "xterm-kitty" {
+            Self::Kitty
+        } else if var("TERM_PROGRAM") == "iTerm.app"
+            || var("TERM_PROGRAM") == "WezTerm"
+            || var("LC_TERMINAL") == "iTerm2"
+        {
+            Self::Iterm
+        } else if var("TERM").contains("sixel") || var("TERM") == "foot" {
+            Self::Sixel
+        } else {
+            Self::Blocks
+        }
+    }
+}
+
+/// Urls of the pictures attached to a plurk, from its html content. Only
+/// the ones uploaded to plurk, other links are up to whoever posted them.
+pub fn image_urls(content: &str) -> Vec<String> {
+    let mut urls: Vec<String> = Vec::new();
+    for seg in parse_html(content) {
+        if let Some(link) = seg.link {
+            let is_image = reqwest::Url::parse(&link)
+                .is_ok_and(|u| u.scheme() == "https" && u.host_str() == Some(IMAGE_HOST));
+            if is_image && !urls.contains(&link) {
+                urls.push(link);
+            }
+        }
+    }
+    urls
+}
+
+/// Print previews of the images in `content`, if enabled. Failures are
+/// reported inline and never abort the caller.
+pub async fn preview(opts: &RenderOptions, content: &str) {
+    if opts.images == ImageMode::Off {
+        return;
+    }
+    for url in image_urls(content) {
+        match fetch(&url).await.and_then(|data| draw(opts, &data)) {
+            Ok(s) => println!("{}", s),
+            Err(e) => println!("[img] {} ({})", url, e),
+        }
+    }
+}
+
+fn cache_file(url: &str) -> Option<PathBuf> {
+    let dir = dirs::cache_dir()?.join("plurk-cli").join("images");
+    Some(dir.join(format!("{:x}", Sha256::digest(url.as_bytes()))))
+}
+
+/// Download an image, or read it from the cache dir. Either way it's given
+/// up past `MAX_IMAGE_BYTES`.
+pub async fn fetch(url: &str) -> Result<Vec<u8>, PlurkError> {
+    let too_large = || PlurkError::IOError(String::from("image too large"));
+    let cache = cache_file(url);
+    if let Some(path) = &cache {
+        if let Ok(meta) = fs::metadata(path) {
+            if meta.len() > MAX_IMAGE_BYTES as u64 {
+                return Err(too_large());
+            }
+            if let Ok(data) = fs::read(path) {
+                return Ok(data);
+            }
+        }
+    }
+
+    let mut resp = reqwest::get(url).await.map_err(PlurkError::ReqwestError)?;
+    if resp
+        .content_length()
+        .is_some_and(|len| len > MAX_IMAGE_BYTES as u64)
+    {
+        return Err(too_large());
+    }
+    // The length may be missing or wrong, count what actually arrives.
+    let mut data = Vec::new();
+    while let Some(chunk) = resp.chunk().await.map_err(PlurkError::ReqwestError)? {
+        if data.len() + chunk.len() > MAX_IMAGE_BYTES {
+            return Err(too_large());
+        }
+        data.extend_from_slice(&chunk);
+    }
+
+    if let Some(path) = cache {
+        if let Some(dir) = path.parent() {
+            let _ = fs::create_dir_all(dir);
+        }
+        let _ = fs::write(path, &data);
+    }
+    Ok(data)
+}
+
+fn draw(opts: &RenderOptions, data: &[u8]) -> Result<String, PlurkError> {
+    let img = image::load_from_memory(data).map_err(|e| PlurkError::ParseError(e.to_string()))?;
+    let cols = u32::from(opts.image_width.max(1));
+    let mode = match opts.images {
+        ImageMode::Auto => ImageMode::detect(),
+        mode => mode,
+    };
+    Ok(match mode {
+        ImageMode::Kitty => kitty(&img, cols)?,
+        ImageMode::Iterm => iterm(data, cols),
+        ImageMode::Sixel => sixel(&img, cols),
+        _ => blocks(&img, cols),
+    })
+}
+
+/// Kitty graphics protocol, sending a png in base64 chunks.
+fn kitty(img: &DynamicImage, cols: u32) -> Result<String, PlurkError> {
+    // Cells are about 10px wide, there's no point sending more than that.
+    let img = shrink(img, cols * 10, u32::MAX);
+    let mut png = Vec::new();
+    img.write_to(&mut Cursor::new(&mut png), ImageFormat::Png)
+        .map_err(|e| PlurkError::ParseError(e.to_string()))?;
+    let encoded = BASE64.encode(png);
+
+    let mut out = String::new();
+    let chunks: Vec<&[u8]> = encoded.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        let chunk = std::str::from_utf8(chunk).unwrap_or_default();
+        if i == 0 {
+            out.push_str(&format!(
+                "\x1b_Ga=T,f=100,c={},m={};{}\x1b\\",
+                cols, more, chunk
+            ));
+        } else {
+            out.push_str(&format!("\x1b_Gm={};{}\x1b\\", more, chunk));
+        }
+    }
+    Ok(out)
+}
+
+/// iTerm2 inline image, the terminal decodes and scales the original file.
+fn iterm(data: &[u8], cols: u32) -> String {
+    format!(
+        "\x1b]1337;File=inline=1;size={};width={};preserveAspectRatio=1:{}\x07",
+        data.len(),
+        cols,
+        BASE64.encode(data)
+    )
+}
+
+/// Sixel graphics with a 6x6x6 color cube palette.
+fn sixel(img: &DynamicImage, cols: u32) -> String {
+    let img = shrink(img, cols * 10, cols * 10).to_rgb8();
+    let (w, h) = img.dimensions();
+    let index = |x: u32, y: u32| {
+        let p = img.get_pixel(x, y);
+        let q = |v: u8| ((u16::from(v) * 5 + 127) / 255) as usize;
+        q(p[0]) * 36 + q(p[1]) * 6 + q(p[2])
+    };
+
+    let mut out = String::from("\x1bPq");
+    for i in 0..216 {
+        let level = |v: usize| v * 100 / 5;
+        out.push_str(&format!(
+            "#{};2;{};{};{}",
+            i,
+            level(i / 36),
+            level(i / 6 % 6),
+            level(i % 6)
+        ));
+    }
+    for band in (0..h).step_by(6) {
+        let rows = (h - band).min(6);
+        let mut used = [false; 216];
+        for y in band..band + rows {
+            for x in 0..w {
+                used[index(x, y)] = true;
+            }
+        }
+        for color in (0..216).filter(|&c| used[c]) {
+            out.push_str(&format!("#{}", color));
+            let mut run: Option<(char, u32)> = None;
+            for x in 0..w {
+                let mut bits = 0u8;
+                for dy in 0..rows {
+                    if index(x, band + dy) == color {
+                        bits |= 1 << dy;
+                    }
+                }
+                let c = char::from(63 + bits);
+                run = match run {
+                    Some((prev, n)) if prev == c => Some((prev, n + 1)),
+                    Some((prev, n)) => {
+                        push_run(&mut out, prev, n);
+                        Some((c, 1))
+                    }
+                    None => Some((c, 1)),
+                };
+            }
+            if let Some((prev, n)) = run {
+                push_run(&mut out, prev, n);
+            }
+            out.push('$');
+        }
+        out.push('-');
+    }
+    out.push_str("\x1b\\");
+    out
+}
+
+fn push_run(out: &mut String, c: char, n: u32) {
+    if n > 3 {
+        out.push_str(&format!("!{}{}", n, c));
+    } else {
+        for _ in 0..n {
+            out.push(c);
+        }
+    }
+}
+
+/// Upper half blocks, each cell showing two pixels in true color.
+fn blocks(img: &DynamicImage, cols: u32) -> String {
+    let img = shrink(img, cols, cols * 2).to_rgb8();
+    let (w, h) = img.dimensions();
+    let mut out = String::new();
+    for y in (0..h).step_by(2) {
+        for x in 0..w {
+            let top = img.get_pixel(x, y);
+            let bottom = if y + 1 < h {
+                img.get_pixel(x, y + 1)
+            } else {
+                top
+            };
+            out.push_str(&format!(
+                "\x1b[38;2;{};{};{}m\x1b[48;2;{};{};{}m▀",
+                top[0], top[1], top[2], bottom[0], bottom[1], bottom[2]
+            ));
+        }
+        out.push_str("\x1b[0m");
+        if y + 2 < h {
+            out.push('\n');
+        }
+    }
+    out
+}
+
+/// Scale down to fit in `max_w` x `max_h` pixels, keeping the aspect ratio.
+fn shrink(img: &DynamicImage, max_w: u32, max_h: u32) -> DynamicImage {
+    let (w, h) = img.dimensions();
+    if w <= max_w && h <= max_h {
+        img.clone()
+    } else {
+        img.resize(max_w, max_h, FilterType::Triangle)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn only_plurk_images() {
+        let content = "<a href=\"https://images.plurk.com/a.jpg\">[img]</a> \
+            <a href=\"https://example.com/b.jpg\">[img]</a> \
+            <a href=\"http://images.plurk.com/c.jpg\">[img]</a> \
+            <a href=\"https://images.plurk.com/a.jpg\">again</a>";
+        assert_eq!(image_urls(content), ["https://images.plurk.com/a.jpg"]);
+    }
+}
@@ -0,0 +1,218 @@
+// layout.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Display width aware wrapping, so CJK text isn't cut mid-glyph.
+
+use unicode_width::UnicodeWidthChar;
+
+const ELLIPSIS: char = '…';
+
+/// A piece of terminal output: an escape sequence takes no columns.
+enum Token<'a> {
+    Escape(&'a str),
+    Char(char),
+}
+
+fn tokens(text: &str) -> Vec<Token<'_>> {
+    let mut out = Vec::new();
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if c != '\x1b' {
+            out.push(Token::Char(c));
+            rest = &rest[c.len_utf8()..];
+            continue;
+        }
+        let len = escape_len(rest);
+        out.push(Token::Escape(&rest[..len]));
+        rest = &rest[len..];
+    }
+    out
+}
+
+/// Length in bytes of the escape sequence at the start of `s`.
+fn escape_len(s: &str) -> usize {
+    let bytes = s.as_bytes();
+    match bytes.get(1) {
+        // CSI: ends with a byte in 0x40..=0x7e
+        Some(b'[') => bytes[2..]
+            .iter()
+            .position(|b| (0x40..=0x7e).contains(b))
+            .map_or(s.len(), |i| i + 3),
+        // OSC, DCS, APC: end with ST (ESC \) or, for OSC, BEL
+        Some(b']') | Some(b'P') | Some(b'_') => {
+            let body = &s[2..];
+            let st = body.find("\x1b\\").map(|i| i + 4);
+            let bel = body.find('\x07').map(|i| i + 3);
+            match (st, bel) {
+                (Some(a), Some(b)) => a.min(b),
+                (Some(a), None) | (None, Some(a)) => a,
+                (None, None) => s.len(),
+            }
+        }
+        Some(_) => 1 + s[1..].chars().next().map_or(0, char::len_utf8),
+        None => 1,
+    }
+}
+
+fn char_width(c: char) -> usize {
+    c.width().unwrap_or(0)
+}
+
+/// Columns taken by `text`, ignoring escape sequences.
+pub fn display_width(text: &str) -> usize {
+    tokens(text)
+        .iter()
+        .map(|t| match t {
+            Token::Char(c) => char_width(*c),
+            Token::Escape(_) => 0,
+        })
+        .sum()
+}
+
+/// Cut `text` to `width` columns, ending with `…` when something was cut.
+pub fn truncate(text: &str, width: usize) -> String {
+    if display_width(text) <= width {
+        return text.to_string();
+    }
+    let mut out = String::new();
+    let mut used = 0;
+    let mut escaped = false;
+    for t in tokens(text) {
+        match t {
+            Token::Escape(e) => {
+                out.push_str(e);
+                escaped = true;
+            }
+            Token::Char(c) => {
+                let w = char_width(c);
+                if used + w + 1 > width {
+                    break;
+                }
+                out.push(c);
+                used += w;
+            }
+        }
+    }
+    if width > 0 {
+        out.push(ELLIPSIS);
+    }
+    if escaped {
+        // Don't let a cut style or hyperlink leak into what follows.
+        out.push_str("\x1b[0m\x1b]8;;\x1b\\");
+    }
+    out
+}
+
+/// Wrap every line of `text` to `width` columns. The first line starts with
+/// `first`, the following ones with `rest`, giving a hanging indent.
+pub fn wrap(text: &str, width: usize, first: &str, rest: &str) -> String {
+    let mut lines: Vec<String> = Vec::new();
+    for (i, line) in text.split('\n').enumerate() {
+        let indent = if i == 0 { first } else { rest };
+        wrap_line(line, width, indent, rest, &mut lines);
+    }
+    lines.join("\n")
+}
+
+fn wrap_line(line: &str, width: usize, first: &str, rest: &str, out: &mut Vec<String>) {
+    let mut indent = first;
+    let mut current = String::from(indent);
+    let mut used = display_width(indent);
+    // Where the line may be broken: byte offset in `current` and its width.
+    let mut brk: Option<(usize, usize)> = None;
+
+    for t in tokens(line) {
+        let c = match t {
+            Token::Escape(e) => {
+                current.push_str(e);
+                continue;
+            }
+            Token::Char(c) => c,
+        };
+        let w = char_width(c);
+        let avail = width.max(display_width(indent) + 2);
+
+        if used + w > avail && used > display_width(indent) {
+            indent = rest;
+            match brk.filter(|_| c != ' ') {
+                Some((at, at_width)) => {
+                    let tail = current.split_off(at);
+                    out.push(current.trim_end().to_string());
+                    current = format!("{}{}", indent, tail.trim_start_matches(' '));
+                    used = display_width(indent) + (used - at_width);
+                }
+                None => {
+                    out.push(current.trim_end().to_string());
+                    current = String::from(indent);
+                    used = display_width(indent);
+                }
+            }
+            brk = None;
+            if c == ' ' {
+                continue;
+            }
+        }
+
+        current.push(c);
+        used += w;
+        // Break after spaces, and anywhere around wide (CJK) glyphs.
+        if c == ' ' || w > 1 {
+            brk = Some((current.len(), used));
+        }
+    }
+    out.push(current);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn width_counts_cjk_twice_and_skips_escapes() {
+        assert_eq!(display_width("abc"), 3);
+        assert_eq!(display_width("噗浪"), 4);
+        assert_eq!(display_width("\x1b[1mhi\x1b[0m"), 2);
+        assert_eq!(
+            display_width("\x1b]8;;https://plurk.com\x1b\\link\x1b]8;;\x1b\\"),
+            4
+        );
+    }
+
+    #[test]
+    fn truncate_keeps_whole_glyphs() {
+        assert_eq!(truncate("hello", 5), "hello");
+        assert_eq!(truncate("hello world", 6), "hello…");
+        // A wide glyph that doesn't fit whole is dropped.
+        assert_eq!(truncate("噗浪噗浪", 6), "噗浪…");
+        assert_eq!(truncate("噗浪噗浪", 5), "噗浪…");
+        assert!(display_width(&truncate("噗浪噗浪", 5)) <= 5);
+    }
+
+    #[test]
+    fn truncate_resets_cut_styles() {
+        let cut = truncate("\x1b[1mbold text\x1b[0m", 5);
+        assert!(cut.starts_with("\x1b[1mbold…"));
+        assert!(cut.ends_with("\x1b[0m\x1b]8;;\x1b\\"));
+    }
+
+    #[test]
+    fn wrap_breaks_at_spaces_with_hanging_indent() {
+        assert_eq!(
+            wrap("one two three four", 10, "", "  "),
+            "one two\n  three\n  four"
+        );
+        assert_eq!(wrap("a\nb", 10, "> ", "  "), "> a\n  b");
+    }
+
+    #[test]
+    fn wrap_breaks_between_cjk_glyphs() {
+        let out = wrap("噗浪噗浪噗浪", 6, "", "");
+        assert_eq!(out, "噗浪噗\n浪噗浪");
+        assert!(out.lines().all(|l| display_width(l) <= 6));
+    }
+
+    #[test]
+    fn wrap_cuts_long_words() {
+        assert_eq!(wrap("abcdefgh", 4, "", ""), "abcd\nefgh");
+    }
+}
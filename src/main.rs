@@ -2,17 +2,54 @@
 // Copyright (C) 2022 dephilia <me@dephilia.moe>
 // Distributed under terms of the MIT license.
 
+mod alerts;
+mod api;
 mod app;
+mod archive;
+mod blocks;
+mod cliques;
 mod comet;
+mod config;
 mod error;
+mod export;
+mod friends;
+mod images;
+mod layout;
+mod oauth;
+mod pager;
 mod plurk;
+mod render;
+mod search;
+mod secret;
+mod shell;
+mod site;
+mod theme;
+mod thread;
+mod time;
+mod tui;
 mod utils;
+mod watch;
 
+use alerts::Answer;
 use app::*;
+use archive::{Archive, SearchFilter};
+use chrono::NaiveDate;
+use chrono_tz::Tz;
 use clap::{CommandFactory, Parser, Subcommand};
+use config::Config;
 use error::PlurkError;
+use friends::{Action, Relation};
+use images::ImageMode;
+use pager::Pager;
 use plurk::Plurk;
-use tokio;
+use render::{ContentMode, RenderOptions};
+use secret::SecretBackend;
+use std::path::PathBuf;
+use std::time::Duration;
+use terminal_size::{terminal_size, Width};
+use theme::ColorMode;
+use time::{cache_dateformat, cached_dateformat, TimeFormat, TimeMode};
+use tokio::signal;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -21,76 +58,824 @@ struct Cli {
     #[command(subcommand)]
     command: Option<Commands>,
 
-    #[arg(short, long, default_value_t = get_key_file_string())]
-    key_file: String,
+    /// Key file, defaults to `key.toml` in the config dir
+    #[arg(short, long)]
+    key_file: Option<String>,
+
+    /// Use the key file of a named profile
+    #[arg(short, long)]
+    profile: Option<String>,
+
+    /// How to print the content of plurks
+    #[arg(long, value_enum, global = true, default_value_t = ContentMode::Rendered)]
+    content: ContentMode,
+
+    /// Preview images attached to plurks
+    #[arg(long, value_enum, global = true, default_value_t = ImageMode::Off)]
+    images: ImageMode,
+
+    /// Width of image previews in columns
+    #[arg(long, global = true, default_value_t = 40)]
+    image_width: u16,
+
+    /// Output width in columns, when it can't be detected or to override it
+    #[arg(long, global = true)]
+    width: Option<u16>,
+
+    /// When to use colors, `auto` honors `NO_COLOR`
+    #[arg(long, value_enum, global = true, default_value_t = ColorMode::Auto)]
+    color: ColorMode,
+
+    /// Don't send long outputs through `$PAGER`
+    #[arg(long, global = true)]
+    no_pager: bool,
+
+    /// How to print timestamps
+    #[arg(long, value_enum, global = true, default_value_t = TimeMode::Absolute)]
+    time: TimeMode,
+
+    /// Time zone of timestamps as an IANA name, e.g. `Asia/Taipei`
+    #[arg(long, global = true)]
+    tz: Option<Tz>,
+
+    /// Where to keep the oauth secrets
+    #[arg(short, long, value_enum, default_value_t = SecretBackend::Toml)]
+    secret_backend: SecretBackend,
 }
 
 #[derive(Subcommand)]
 enum Commands {
-    GenKey {
-        consumer_key: String,
-        consumer_secret: String,
-        token_key: Option<String>,
-        token_secret: Option<String>,
+    /// Create the key file and authorize plurk-cli
+    Init {
+        consumer_key: Option<String>,
+        consumer_secret: Option<String>,
+        /// Overwrite an existing key file
+        #[arg(short, long)]
+        force: bool,
+        /// Use the PIN flow instead of the local callback server
+        #[arg(long)]
+        no_browser: bool,
     },
     Comet,
 
     Me,
 
+    /// Authorize plurk-cli and save the oauth token
+    Login {
+        /// Use the PIN flow instead of the local callback server
+        #[arg(long)]
+        no_browser: bool,
+        /// Only print the authorization url, don't launch the browser
+        #[arg(long)]
+        no_open: bool,
+        /// Port of the local callback server, 0 picks a free one
+        #[arg(long, default_value_t = 0)]
+        port: u16,
+        /// Seconds to wait for the authorization
+        #[arg(long, default_value_t = 300)]
+        timeout: u64,
+    },
+
+    /// Show a plurk and its responses
+    Show {
+        /// Plurk slug, url, or decimal id as `id:<id>`
+        id: String,
+        /// Keep responses in chronological order instead of a tree
+        #[arg(long)]
+        flat: bool,
+        /// Only show responses newer than this response id, and those they reply to
+        #[arg(long)]
+        since_response: Option<u64>,
+    },
+
+    /// Follow the new responses of a plurk
+    Watch {
+        /// Plurk slug, url, or decimal id as `id:<id>`
+        id: String,
+        /// Seconds between polls of the responses, which also cover plurks
+        /// comet doesn't report
+        #[arg(long, default_value_t = 30)]
+        interval: u64,
+    },
+
+    /// Search plurks
+    Search {
+        /// Words that must all appear
+        terms: Vec<String>,
+        /// Search the local archive instead of plurk
+        #[arg(long)]
+        local: bool,
+        /// Only plurks and responses by this nickname (local)
+        #[arg(long, requires = "local")]
+        author: Option<String>,
+        /// Only this qualifier, e.g. `says` (local)
+        #[arg(long, requires = "local")]
+        qualifier: Option<String>,
+        /// From this day, `YYYY-MM-DD` (local)
+        #[arg(long, requires = "local")]
+        since: Option<NaiveDate>,
+        /// Up to this day included, `YYYY-MM-DD` (local)
+        #[arg(long, requires = "local")]
+        until: Option<NaiveDate>,
+        /// Only responses (local)
+        #[arg(long, requires = "local")]
+        responses: bool,
+        /// Stop after this many results, 20 for local searches
+        #[arg(short, long)]
+        limit: Option<u64>,
+        #[arg(short, long)]
+        verbose: bool,
+    },
+
+    /// Post a plurk
+    Post {
+        /// Text of the plurk
+        #[arg(required = true)]
+        content: Vec<String>,
+        /// Qualifier, e.g. `says` or `thinks`, `:` for none
+        #[arg(short, long, default_value = ":")]
+        qualifier: String,
+        /// Only show it to the members of a clique
+        #[arg(long, conflicts_with = "friends_only")]
+        to_clique: Option<String>,
+        /// Only show it to these users, as comma separated nicknames
+        #[arg(long, value_delimiter = ',', conflicts_with = "friends_only")]
+        to: Vec<String>,
+        /// Only show it to your friends
+        #[arg(long)]
+        friends_only: bool,
+    },
+
+    /// Find plurk users
+    Users {
+        #[command(subcommand)]
+        command: UsersCommands,
+    },
+
+    /// List the friends of a user, yours by default
+    Friends {
+        /// Nickname or id of the user
+        user: Option<String>,
+        #[arg(short, long)]
+        limit: Option<u64>,
+    },
+
+    /// List the fans of a user, yours by default
+    Fans {
+        /// Nickname or id of the user
+        user: Option<String>,
+        #[arg(short, long)]
+        limit: Option<u64>,
+    },
+
+    /// List the users you follow
+    Following {
+        #[arg(short, long)]
+        limit: Option<u64>,
+    },
+
+    /// Send a friend request
+    Befriend {
+        nick: String,
+    },
+
+    /// Remove a user from your friends
+    Unfriend {
+        nick: String,
+    },
+
+    /// Become a fan of a user
+    Fan {
+        nick: String,
+    },
+
+    /// Follow the plurks of a user on your timeline
+    Follow {
+        nick: String,
+    },
+
+    /// Stop following a user
+    Unfollow {
+        nick: String,
+    },
+
+    /// Manage the cliques plurks can be limited to
+    Cliques {
+        #[command(subcommand)]
+        command: CliquesCommands,
+    },
+
+    /// List, block or unblock users
+    Blocks {
+        #[command(subcommand)]
+        command: BlocksCommands,
+    },
+
+    /// Show alerts and answer friend requests
+    Alerts {
+        /// Past alerts instead of the active ones
+        #[arg(long)]
+        history: bool,
+        #[command(subcommand)]
+        command: Option<AlertsCommands>,
+    },
+
+    /// Local archive of the plurks seen
+    Archive {
+        #[command(subcommand)]
+        command: ArchiveCommands,
+    },
+
+    /// Back up your plurks, their responses and images to a folder
+    Export {
+        /// Folder of the export, an interrupted export in it is resumed
+        #[arg(short, long)]
+        out: PathBuf,
+        /// Export the whole timeline, not only your plurks
+        #[arg(long)]
+        all: bool,
+        /// Don't download the attached images
+        #[arg(long)]
+        no_images: bool,
+        /// Also render the export as a static site in its `html` folder
+        #[arg(long)]
+        html: bool,
+    },
+
+    /// Load a previous export into the archive
+    Import {
+        /// Folder written by `plurk export`
+        archive: PathBuf,
+    },
+
+    /// Inspect, test or revoke the oauth token
+    Auth {
+        #[command(subcommand)]
+        command: AuthCommands,
+    },
+
     Timeline {
         #[arg(short, long)]
         verbose: bool,
         #[arg(short, long, default_value_t = 20)]
         limit: u64,
     },
+
+    /// Interactive shell keeping one session and live updates
+    Shell,
+
+    /// Full-screen timeline reader with live updates
+    Tui {
+        #[arg(short, long, default_value_t = 50)]
+        limit: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum UsersCommands {
+    /// Search users by name
+    Search {
+        name: String,
+        #[arg(short, long, default_value_t = 20)]
+        limit: u64,
+    },
+}
+
+#[derive(Subcommand)]
+enum CliquesCommands {
+    /// List your cliques
+    List,
+    /// List the members of a clique
+    Show { name: String },
+    /// Create an empty clique
+    Create { name: String },
+    /// Rename a clique
+    Rename { name: String, new_name: String },
+    /// Add users to a clique
+    Add {
+        name: String,
+        #[arg(required = true)]
+        nicks: Vec<String>,
+    },
+    /// Remove users from a clique
+    Remove {
+        name: String,
+        #[arg(required = true)]
+        nicks: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum BlocksCommands {
+    /// List the blocked users, refreshing the local mirror when the whole
+    /// list is shown
+    List {
+        /// Stop after this many users, leaving the local mirror as it was
+        #[arg(short, long)]
+        limit: Option<u64>,
+    },
+    /// Block users
+    Add {
+        #[arg(required = true)]
+        nicks: Vec<String>,
+    },
+    /// Unblock users
+    Remove {
+        #[arg(required = true)]
+        nicks: Vec<String>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AlertsCommands {
+    /// Accept the friend request of a user
+    Accept { nick: String },
+    /// Deny the friend request of a user
+    Deny { nick: String },
+    /// Accept a user as a fan instead of a friend
+    Fan { nick: String },
+    /// Accept every pending friend request
+    AcceptAll {
+        /// As fans instead of friends
+        #[arg(long)]
+        fans: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum ArchiveCommands {
+    /// Backfill the archive with your plurks and their responses
+    Sync {
+        /// Stop after this many plurks
+        #[arg(short, long)]
+        limit: Option<u64>,
+        /// Don't fetch the responses
+        #[arg(long)]
+        no_responses: bool,
+    },
+    /// Show what the archive holds
+    Stats,
+    /// Report your archived plurks since deleted, edited or responded to
+    Diff {
+        /// Only compare this many recent plurks
+        #[arg(short, long)]
+        limit: Option<u64>,
+    },
+}
+
+#[derive(Subcommand)]
+enum AuthCommands {
+    /// Check the stored token with the server
+    Status,
+    /// Revoke the token and remove it from the key file
+    Expire,
+    /// Verify request signing and the clock skew against the server
+    Test,
+}
+
+impl Commands {
+    /// Whether what the command sees goes to the archive.
+    fn archives(&self) -> bool {
+        matches!(
+            self,
+            Commands::Comet
+                | Commands::Show { .. }
+                | Commands::Watch { .. }
+                | Commands::Timeline { .. }
+                | Commands::Search { local: false, .. }
+                | Commands::Users { .. }
+                | Commands::Friends { .. }
+                | Commands::Fans { .. }
+                | Commands::Following { .. }
+        )
+    }
+
+    /// Whether the command prints plurks with their timestamps.
+    fn prints_plurks(&self) -> bool {
+        matches!(
+            self,
+            Commands::Comet
+                | Commands::Show { .. }
+                | Commands::Watch { .. }
+                | Commands::Timeline { .. }
+                | Commands::Search { local: false, .. }
+                | Commands::Shell
+                | Commands::Tui { .. }
+        )
+    }
+
+    /// Whether the command only reads local data, so needs no credentials.
+    fn offline(&self) -> bool {
+        matches!(
+            self,
+            Commands::Search { local: true, .. } | Commands::Import { .. }
+        )
+    }
+
+    /// Whether the output is finite and may be long enough for a pager.
+    fn pages(&self) -> bool {
+        matches!(
+            self,
+            Commands::Me
+                | Commands::Show { .. }
+                | Commands::Timeline { .. }
+                | Commands::Search { .. }
+                | Commands::Users { .. }
+                | Commands::Friends { .. }
+                | Commands::Fans { .. }
+                | Commands::Following { .. }
+                | Commands::Alerts { command: None, .. }
+        )
+    }
+}
+
+impl Cli {
+    fn key_file(&self) -> Result<String, PlurkError> {
+        match &self.key_file {
+            Some(path) => Ok(path.clone()),
+            None => Ok(get_key_file(self.profile.as_deref())?
+                .to_string_lossy()
+                .into_owned()),
+        }
+    }
+
+    async fn save_token(&self, plurk: &Plurk, use_env: bool) -> Result<(), PlurkError> {
+        if use_env {
+            plurk.print_token_env();
+            Ok(())
+        } else {
+            self.secret_backend.store(plurk, &self.key_file()?).await
+        }
+    }
+
+    fn render_options(&self, config: &Config) -> RenderOptions {
+        RenderOptions {
+            content: self.content,
+            images: self.images,
+            image_width: self.image_width,
+            // Resolve it now, stdout is no longer the terminal once paging.
+            width: self
+                .width
+                .or_else(|| terminal_size().map(|(Width(w), _)| w)),
+            time: TimeFormat {
+                mode: self.time,
+                tz: self.tz,
+                format: config.date_format.clone(),
+            },
+            theme: config.theme.clone(),
+            blocked: blocks::load_mirror(),
+        }
+    }
+
+    fn pager(&self, command: &Commands, config: &Config) -> Option<Pager> {
+        if command.pages() && config.pager && !self.no_pager {
+            Pager::start()
+        } else {
+            None
+        }
+    }
+}
+
+/// Run a command that only reads local data, without loading credentials.
+fn run_offline(cli: &Cli, config: &Config, command: &Commands) -> Result<(), PlurkError> {
+    let archive = Archive::open()?;
+    match command {
+        Commands::Import { archive: dir } => export::import(&archive, dir),
+        Commands::Search {
+            terms,
+            author,
+            qualifier,
+            since,
+            until,
+            responses,
+            limit,
+            verbose,
+            ..
+        } => {
+            let opts = cli.render_options(config);
+            let filter = SearchFilter {
+                author: author.clone(),
+                qualifier: qualifier.clone(),
+                since: since.and_then(|d| opts.time.day_start(d)),
+                until: until
+                    .and_then(|d| d.succ_opt())
+                    .and_then(|d| opts.time.day_start(d)),
+                responses_only: *responses,
+                limit: limit.unwrap_or(20),
+            };
+            let _pager = cli.pager(command, config);
+            search::print_local(&archive, terms, &filter, *verbose, &opts)
+        }
+        _ => Ok(()),
+    }
 }
 
 #[tokio::main]
 async fn main() -> Result<(), PlurkError> {
     let cli = Cli::parse();
+    cli.color.apply();
 
-    /* Gen Key need to put here */
-    if let Some(Commands::GenKey {
+    let key_file = cli.key_file()?;
+
+    if let Some(Commands::Init {
         consumer_key,
         consumer_secret,
-        token_key,
-        token_secret,
+        force,
+        no_browser,
     }) = &cli.command
     {
-        gen_key_file(
+        return init(
+            cli.secret_backend,
+            &key_file,
             consumer_key.clone(),
             consumer_secret.clone(),
-            token_key.clone(),
-            token_secret.clone(),
-        )?;
-        return Ok(());
+            *force,
+            *no_browser,
+        )
+        .await;
     }
 
-    let mut plurk = Plurk::from_toml(&cli.key_file)?;
+    let config = Config::load()?;
+    if let Some(command) = cli.command.as_ref().filter(|c| c.offline()) {
+        return run_offline(&cli, &config, command);
+    }
+
+    // Credentials from the environment take precedence over the default key
+    // file, but not over one given with `--key-file` or `--profile`.
+    let from_env = if cli.key_file.is_some() || cli.profile.is_some() {
+        None
+    } else {
+        Plurk::from_env()?
+    };
+    let use_env = from_env.is_some();
+    let mut plurk = match from_env {
+        Some(plurk) => plurk,
+        None if !cli.secret_backend.exists(&key_file).await => {
+            return Err(PlurkError::IOError(format!(
+                "{} not found, run `plurk init` first",
+                key_file
+            )));
+        }
+        None => cli.secret_backend.load(&key_file).await?,
+    };
+
+    if let Some(Commands::Login {
+        no_browser,
+        no_open,
+        port,
+        timeout,
+    }) = &cli.command
+    {
+        login(
+            &mut plurk,
+            *no_browser,
+            *no_open,
+            *port,
+            Duration::from_secs(*timeout),
+        )
+        .await?;
+        cli.save_token(&plurk, use_env).await?;
+        // Refresh the date order, it may have changed on plurk since.
+        match plurk.get_me().await {
+            Ok(me) => cache_dateformat(me.dateformat)?,
+            Err(e) => eprintln!("Warning: cannot get the date order of your settings: {}", e),
+        }
+        println!("Login succeeded");
+        return Ok(());
+    }
 
     if !plurk.has_token() {
         plurk.acquire_plurk_key().await?;
-        plurk.to_toml(&cli.key_file)?;
+        cli.save_token(&plurk, use_env).await?;
     }
 
-    match &cli.command {
-        Some(Commands::GenKey { .. }) => {
-            // Bypass here
-        }
-        Some(Commands::Me) => {
-            print_me(plurk.clone()).await?;
-        }
-        Some(Commands::Comet) => {
-            poll_comet(plurk.clone()).await?;
-        }
-        Some(Commands::Timeline { verbose, limit }) => {
-            print_timeline(plurk.clone(), verbose.clone(), limit.clone()).await?;
-        }
-        None => {
-            let mut cmd = Cli::command();
-            cmd.print_help().unwrap();
-        }
+    let mut opts = cli.render_options(&config);
+    if opts.time.mode == TimeMode::Absolute
+        && opts.time.format.is_none()
+        && cli.command.as_ref().is_some_and(Commands::prints_plurks)
+    {
+        // Without a configured format, use the date order picked on plurk.
+        let dateformat = match cached_dateformat() {
+            Some(dateformat) => Some(dateformat),
+            None => match plurk.get_me().await {
+                Ok(me) => {
+                    if let Err(e) = cache_dateformat(me.dateformat) {
+                        eprintln!("Warning: {}", e);
+                    }
+                    Some(me.dateformat)
+                }
+                Err(e) => {
+                    eprintln!("Warning: cannot get the date order of your settings: {}", e);
+                    None
+                }
+            },
+        };
+        opts.time.format = dateformat.map(TimeFormat::user_format);
     }
 
-    Ok(())
+    let archive = match &cli.command {
+        Some(command) if command.archives() && config.archive => match Archive::open() {
+            Ok(archive) => Some(archive),
+            Err(e) => {
+                eprintln!("Warning: {}", e);
+                None
+            }
+        },
+        _ => None,
+    };
+
+    let pager = cli
+        .command
+        .as_ref()
+        .and_then(|command| cli.pager(command, &config));
+
+    let run = async {
+        match &cli.command {
+            Some(Commands::Init { .. })
+            | Some(Commands::Login { .. })
+            | Some(Commands::Search { local: true, .. })
+            | Some(Commands::Import { .. }) => {
+                // Bypass here
+            }
+            Some(Commands::Me) => {
+                print_me(plurk.clone()).await?;
+            }
+            Some(Commands::Comet) => {
+                poll_comet(plurk.clone(), archive.as_ref(), &opts).await?;
+            }
+            Some(Commands::Show {
+                id,
+                flat,
+                since_response,
+            }) => {
+                print_show(
+                    plurk.clone(),
+                    id,
+                    *flat,
+                    *since_response,
+                    archive.as_ref(),
+                    &opts,
+                )
+                .await?;
+            }
+            Some(Commands::Watch { id, interval }) => {
+                watch::run_watch(
+                    plurk.clone(),
+                    id,
+                    Duration::from_secs(*interval),
+                    archive.as_ref(),
+                    &opts,
+                )
+                .await?;
+            }
+            Some(Commands::Search {
+                terms,
+                limit,
+                verbose,
+                ..
+            }) => {
+                search::print_remote(&plurk, terms, *limit, *verbose, archive.as_ref(), &opts)
+                    .await?;
+            }
+            Some(Commands::Users { command }) => match command {
+                UsersCommands::Search { name, limit } => {
+                    search::print_users(&plurk, name, *limit, archive.as_ref(), &opts).await?
+                }
+            },
+            Some(Commands::Friends { user, limit }) => {
+                let (user, archive) = (user.as_deref(), archive.as_ref());
+                friends::print_relations(&plurk, Relation::Friends, user, *limit, archive, &opts)
+                    .await?
+            }
+            Some(Commands::Fans { user, limit }) => {
+                let (user, archive) = (user.as_deref(), archive.as_ref());
+                friends::print_relations(&plurk, Relation::Fans, user, *limit, archive, &opts)
+                    .await?
+            }
+            Some(Commands::Following { limit }) => {
+                let archive = archive.as_ref();
+                friends::print_relations(&plurk, Relation::Following, None, *limit, archive, &opts)
+                    .await?
+            }
+            Some(Commands::Befriend { nick }) => {
+                friends::act(&plurk, Action::Befriend, nick).await?
+            }
+            Some(Commands::Unfriend { nick }) => {
+                friends::act(&plurk, Action::Unfriend, nick).await?
+            }
+            Some(Commands::Fan { nick }) => friends::act(&plurk, Action::Fan, nick).await?,
+            Some(Commands::Follow { nick }) => friends::act(&plurk, Action::Follow, nick).await?,
+            Some(Commands::Unfollow { nick }) => {
+                friends::act(&plurk, Action::Unfollow, nick).await?
+            }
+            Some(Commands::Post {
+                content,
+                qualifier,
+                to_clique,
+                to,
+                friends_only,
+            }) => {
+                let limited_to =
+                    cliques::limited_to(&plurk, to_clique.as_deref(), to, *friends_only).await?;
+                post(&plurk, &content.join(" "), qualifier, limited_to.as_deref()).await?;
+            }
+            Some(Commands::Cliques { command }) => match command {
+                CliquesCommands::List => cliques::print_cliques(&plurk).await?,
+                CliquesCommands::Show { name } => {
+                    cliques::print_clique(&plurk, name, &opts).await?
+                }
+                CliquesCommands::Create { name } => cliques::create(&plurk, name).await?,
+                CliquesCommands::Rename { name, new_name } => {
+                    cliques::rename(&plurk, name, new_name).await?
+                }
+                CliquesCommands::Add { name, nicks } => {
+                    cliques::update(&plurk, name, nicks, false).await?
+                }
+                CliquesCommands::Remove { name, nicks } => {
+                    cliques::update(&plurk, name, nicks, true).await?
+                }
+            },
+            Some(Commands::Blocks { command }) => match command {
+                BlocksCommands::List { limit } => {
+                    blocks::print_blocks(&plurk, *limit, &opts).await?
+                }
+                BlocksCommands::Add { nicks } => blocks::update(&plurk, nicks, false).await?,
+                BlocksCommands::Remove { nicks } => blocks::update(&plurk, nicks, true).await?,
+            },
+            Some(Commands::Alerts { history, command }) => match command {
+                None => alerts::print_alerts(&plurk, *history, &opts).await?,
+                Some(AlertsCommands::Accept { nick }) => {
+                    alerts::answer(&plurk, Answer::Accept, nick).await?
+                }
+                Some(AlertsCommands::Deny { nick }) => {
+                    alerts::answer(&plurk, Answer::Deny, nick).await?
+                }
+                Some(AlertsCommands::Fan { nick }) => {
+                    alerts::answer(&plurk, Answer::Fan, nick).await?
+                }
+                Some(AlertsCommands::AcceptAll { fans }) => {
+                    alerts::accept_all(&plurk, *fans).await?
+                }
+            },
+            Some(Commands::Archive { command }) => {
+                let archive = &Archive::open()?;
+                match command {
+                    ArchiveCommands::Sync {
+                        limit,
+                        no_responses,
+                    } => archive::sync(&plurk, archive, *limit, !*no_responses).await?,
+                    ArchiveCommands::Stats => archive::print_stats(&plurk, archive).await?,
+                    ArchiveCommands::Diff { limit } => {
+                        archive::diff(&plurk, archive, *limit, &opts).await?
+                    }
+                }
+            }
+            Some(Commands::Export {
+                out,
+                all,
+                no_images,
+                html,
+            }) => {
+                export::export(&plurk, out, *all, !*no_images).await?;
+                if *html {
+                    site::render(out, &opts.time).await?;
+                }
+            }
+            Some(Commands::Auth { command }) => match command {
+                AuthCommands::Status => print_auth_status(plurk.clone()).await?,
+                AuthCommands::Expire => {
+                    expire_auth(&mut plurk).await?;
+                    if !use_env {
+                        cli.secret_backend.store(&plurk, &key_file).await?;
+                    }
+                }
+                AuthCommands::Test => test_auth(plurk.clone()).await?,
+            },
+            Some(Commands::Timeline { verbose, limit }) => {
+                print_timeline(plurk.clone(), *verbose, *limit, archive.as_ref(), &opts).await?;
+            }
+            Some(Commands::Shell) => {
+                shell::run_shell(plurk.clone(), &opts).await?;
+            }
+            Some(Commands::Tui { limit }) => {
+                tui::run_tui(plurk.clone(), *limit, &opts).await?;
+            }
+            None => {
+                let mut cmd = Cli::command();
+                cmd.print_help().unwrap();
+            }
+        }
+        Ok::<(), PlurkError>(())
+    };
+    let result = if pager.is_some() {
+        // Ctrl-C stops us, but the pager still gets to exit cleanly.
+        tokio::select! {
+            r = run => r,
+            _ = signal::ctrl_c() => Ok(()),
+        }
+    } else {
+        run.await
+    };
+    drop(pager);
+    result
 }
@@ -0,0 +1,127 @@
+// oauth.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+
+use crate::error::PlurkError;
+use reqwest::Url;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpListener;
+
+const CALLBACK_PATH: &str = "/callback";
+const CALLBACK_DONE: &str = "<html><body><h3>plurk-cli is authorized.</h3>\
+    You can close this window and return to the terminal.</body></html>";
+
+/// A one-shot HTTP listener on 127.0.0.1 receiving the oauth redirect.
+pub struct CallbackListener {
+    listener: TcpListener,
+}
+
+impl CallbackListener {
+    pub async fn bind(port: u16) -> Result<Self, PlurkError> {
+        let listener = TcpListener::bind(("127.0.0.1", port))
+            .await
+            .map_err(|e| PlurkError::IOError(format!("Cannot listen on 127.0.0.1: {}", e)))?;
+        Ok(Self { listener })
+    }
+
+    pub fn callback_url(&self) -> Result<String, PlurkError> {
+        let addr = self
+            .listener
+            .local_addr()
+            .map_err(|e| PlurkError::IOError(e.to_string()))?;
+        Ok(format!("http://{}{}", addr, CALLBACK_PATH))
+    }
+
+    /// Serve requests until the redirect for `oauth_token` arrives, and
+    /// return its `oauth_verifier`. Unrelated requests get a 404.
+    pub async fn wait_verifier(&self, oauth_token: &str) -> Result<String, PlurkError> {
+        loop {
+            let (mut stream, _) = self
+                .listener
+                .accept()
+                .await
+                .map_err(|e| PlurkError::IOError(e.to_string()))?;
+
+            let mut request_line = String::new();
+            {
+                let mut reader = BufReader::new(&mut stream);
+                if reader.read_line(&mut request_line).await.is_err() {
+                    continue;
+                }
+            }
+
+            match parse_verifier(&request_line, oauth_token) {
+                Some(verifier) => {
+                    let _ = respond(&mut stream, "200 OK", CALLBACK_DONE).await;
+                    return Ok(verifier);
+                }
+                None => {
+                    let _ = respond(&mut stream, "404 Not Found", "").await;
+                }
+            }
+        }
+    }
+}
+
+/// Extract the verifier from a request line like
+/// `GET /callback?oauth_token=..&oauth_verifier=.. HTTP/1.1`, when its token
+/// is `oauth_token`.
+fn parse_verifier(request_line: &str, oauth_token: &str) -> Option<String> {
+    let target = request_line.split_whitespace().nth(1)?;
+    let url = Url::parse(&format!("http://localhost{}", target)).ok()?;
+    if url.path() != CALLBACK_PATH {
+        return None;
+    }
+    let mut token = None;
+    let mut verifier = None;
+    for (k, v) in url.query_pairs() {
+        match k.as_ref() {
+            "oauth_token" => token = Some(v.into_owned()),
+            "oauth_verifier" => verifier = Some(v.into_owned()),
+            _ => {}
+        }
+    }
+    // Only the redirect for our request token, other local clients could
+    // otherwise hand us a verifier.
+    if token.as_deref() != Some(oauth_token) {
+        return None;
+    }
+    verifier
+}
+
+async fn respond<W: AsyncWriteExt + Unpin>(
+    stream: &mut W,
+    status: &str,
+    body: &str,
+) -> std::io::Result<()> {
+    let resp = format!(
+        "HTTP/1.1 {}\r\nContent-Type: text/html; charset=utf-8\r\n\
+        Content-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    stream.write_all(resp.as_bytes()).await?;
+    stream.shutdown().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn verifier_of_the_request_token() {
+        let line = "GET /callback?oauth_token=abc&oauth_verifier=123 HTTP/1.1";
+        assert_eq!(parse_verifier(line, "abc").as_deref(), Some("123"));
+    }
+
+    #[test]
+    fn verifier_needs_the_request_token() {
+        let other = "GET /callback?oauth_token=xyz&oauth_verifier=123 HTTP/1.1";
+        assert_eq!(parse_verifier(other, "abc"), None);
+        let missing = "GET /callback?oauth_verifier=123 HTTP/1.1";
+        assert_eq!(parse_verifier(missing, "abc"), None);
+        let elsewhere = "GET /other?oauth_token=abc&oauth_verifier=123 HTTP/1.1";
+        assert_eq!(parse_verifier(elsewhere, "abc"), None);
+    }
+}
@@ -0,0 +1,84 @@
+// pager.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Send long outputs through `$PAGER`, like git does.
+
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Child, Command, Stdio};
+
+const DEFAULT_PAGER: &str = "less -R";
+
+/// A running pager receiving our stdout. Dropping it closes the pipe and
+/// waits for the pager to exit.
+pub struct Pager {
+    child: Child,
+}
+
+impl Pager {
+    /// Start the pager if stdout is a terminal. Returns `None` when paging
+    /// doesn't apply or the pager can't be started, output then goes to the
+    /// terminal as usual.
+    pub fn start() -> Option<Self> {
+        if !io::stdout().is_terminal() {
+            return None;
+        }
+        let cmd = env::var("PAGER")
+            .ok()
+            .filter(|p| !p.trim().is_empty())
+            .unwrap_or_else(|| String::from(DEFAULT_PAGER));
+        if cmd.trim() == "cat" {
+            return None;
+        }
+        Self::spawn(&cmd)
+    }
+
+    #[cfg(unix)]
+    fn spawn(cmd: &str) -> Option<Self> {
+        use std::os::fd::AsRawFd;
+
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(cmd).stdin(Stdio::piped());
+        // Quit when it fits on one screen, keep colors, don't clear the screen.
+        if env::var_os("LESS").is_none() {
+            command.env("LESS", "FRX");
+        }
+        let mut child = command.spawn().ok()?;
+        let stdin = child.stdin.take()?;
+
+        let _ = io::stdout().flush();
+        // SAFETY: plain fd and signal calls with valid arguments.
+        unsafe {
+            if libc::dup2(stdin.as_raw_fd(), libc::STDOUT_FILENO) < 0 {
+                let _ = child.kill();
+                return None;
+            }
+            // Quitting the pager early should end us quietly, not panic in
+            // `println!` on a broken pipe.
+            libc::signal(libc::SIGPIPE, libc::SIG_DFL);
+        }
+        Some(Pager { child })
+    }
+
+    #[cfg(not(unix))]
+    fn spawn(_cmd: &str) -> Option<Self> {
+        None
+    }
+}
+
+impl Drop for Pager {
+    fn drop(&mut self) {
+        let _ = io::stdout().flush();
+        // Point stdout away from the pipe so the pager sees the end of input.
+        #[cfg(unix)]
+        unsafe {
+            // SAFETY: the path is a valid C string and the fds are ours.
+            let null = libc::open(c"/dev/null".as_ptr(), libc::O_WRONLY);
+            if null >= 0 {
+                libc::dup2(null, libc::STDOUT_FILENO);
+                libc::close(null);
+            }
+        }
+        let _ = self.child.wait();
+    }
+}
@@ -4,22 +4,32 @@
 // Plurk API doc: https://www.plurk.com/API
 
 use crate::error::PlurkError;
+use crate::oauth::CallbackListener;
 use crate::utils::*;
 use chrono::{self, DateTime, FixedOffset};
-use reqwest;
-use reqwest_oauth1::{OAuthClientProvider, Secrets, TokenReaderFuture};
+use reqwest_oauth1::{OAuthClientProvider, Secrets, TokenReaderFuture, TokenResponse};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
+use std::env;
 use std::fmt;
 use std::fs::File;
 use std::io;
 use std::io::prelude::*;
+use std::io::IsTerminal;
 use std::path::Path;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, BufReader};
 
 const REQUEST_TOKEN_URL: &str = "/OAuth/request_token";
 const AUTHORIZE_URL: &str = "/OAuth/authorize";
 const ACCESS_TOKEN_URL: &str = "/OAuth/access_token";
 const BASE_URL: &str = "https://www.plurk.com";
 
+const ENV_CONSUMER_KEY: &str = "PLURK_CONSUMER_KEY";
+const ENV_CONSUMER_SECRET: &str = "PLURK_CONSUMER_SECRET";
+const ENV_TOKEN_KEY: &str = "PLURK_TOKEN_KEY";
+const ENV_TOKEN_SECRET: &str = "PLURK_TOKEN_SECRET";
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct Plurk {
     consumer: PlurkKeys,
@@ -32,34 +42,47 @@ pub struct PlurkKeys {
     secret: String,
 }
 
-#[derive(Deserialize, Debug)]
+/// A plurk user. Search results, friend lists and alerts leave out some of
+/// the fields, which then take their defaults.
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 #[allow(dead_code)]
 pub struct PlurkUser {
     pub id: u64,
     pub nick_name: String,
+    #[serde(default)]
     pub display_name: String,
     pub full_name: Option<String>,
     pub avatar: Option<u64>,
     pub date_of_birth: Option<WrappedDT>,
+    #[serde(default)]
     pub dateformat: u8,
+    #[serde(default)]
     pub default_lang: String,
+    #[serde(default)]
     pub friend_list_privacy: String,
+    #[serde(default)]
     pub gender: u8,
+    #[serde(default)]
     pub has_profile_image: u8,
+    #[serde(default)]
     pub karma: f32,
     pub name_color: Option<String>,
+    #[serde(default)]
     pub premium: bool,
+    #[serde(default)]
     pub status: String,
+    #[serde(default)]
     pub timeline_privacy: u8,
     pub uid: Option<u64>,
+    #[serde(default)]
     pub verified_account: bool,
 }
 
-#[derive(Deserialize, Debug)]
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
 #[allow(dead_code)]
 pub struct PlurkData {
     pub plurk_id: u64,
-    #[serde(deserialize_with = "from_rfc2822")]
+    #[serde(deserialize_with = "from_rfc2822", serialize_with = "to_rfc2822")]
     pub posted: DateTime<FixedOffset>,
     pub content: String,
     pub content_raw: String,
@@ -94,6 +117,29 @@ pub struct PlurkData {
     pub last_edited: Option<WrappedDT>,
 }
 
+#[derive(Deserialize, Serialize, Debug, Clone, Default)]
+#[allow(dead_code)]
+pub struct PlurkResponse {
+    pub id: u64,
+    pub plurk_id: u64,
+    pub user_id: u64,
+    #[serde(deserialize_with = "from_rfc2822", serialize_with = "to_rfc2822")]
+    pub posted: DateTime<FixedOffset>,
+    pub qualifier: String,
+    pub content: String,
+    pub content_raw: String,
+    pub lang: String,
+    #[serde(default)]
+    pub editability: u8,
+    pub last_edited: Option<WrappedDT>,
+}
+
+/// Error body returned by the Plurk API with non-2xx statuses.
+#[derive(Deserialize, Debug)]
+struct ApiErrorText {
+    error_text: String,
+}
+
 impl Plurk {
     pub fn new(
         consumer_key: String,
@@ -122,34 +168,84 @@ impl Plurk {
             }
         }
     }
+    /// Build from `PLURK_CONSUMER_KEY`/`PLURK_CONSUMER_SECRET` and the optional
+    /// `PLURK_TOKEN_KEY`/`PLURK_TOKEN_SECRET`. Returns `None` when none of them
+    /// is set, and an error when only some are.
+    pub fn from_env() -> Result<Option<Self>, PlurkError> {
+        let names = [
+            ENV_CONSUMER_KEY,
+            ENV_CONSUMER_SECRET,
+            ENV_TOKEN_KEY,
+            ENV_TOKEN_SECRET,
+        ];
+        let [consumer_key, consumer_secret, token_key, token_secret] =
+            names.map(|name| env::var(name).ok().filter(|v| !v.is_empty()));
+        let vars = [&consumer_key, &consumer_secret, &token_key, &token_secret];
+        if vars.iter().all(|v| v.is_none()) {
+            return Ok(None);
+        }
+
+        // The token is optional, but comes as a pair.
+        let required = if token_key.is_some() || token_secret.is_some() {
+            names.len()
+        } else {
+            2
+        };
+        let missing: Vec<&str> = names[..required]
+            .iter()
+            .zip(vars)
+            .filter(|(_, v)| v.is_none())
+            .map(|(name, _)| *name)
+            .collect();
+        match (consumer_key, consumer_secret) {
+            (Some(key), Some(secret)) if missing.is_empty() => {
+                Ok(Some(Plurk::new(key, secret, token_key, token_secret)))
+            }
+            _ => Err(PlurkError::SecretError(format!(
+                "incomplete credentials in the environment, {} not set",
+                missing.join(", ")
+            ))),
+        }
+    }
+
     pub fn from_toml(path: &str) -> Result<Self, PlurkError> {
         let path = Path::new(path);
         let display = path.display();
 
-        let mut file =
-            File::open(&path).map_err(|_| PlurkError::IOError(format!("{}", display)))?;
+        let mut file = File::open(path).map_err(|_| PlurkError::IOError(format!("{}", display)))?;
+        warn_if_exposed(path);
 
         let mut s = String::new();
         file.read_to_string(&mut s)
             .map_err(|_| PlurkError::IOError(format!("{}", display)))?;
 
-        toml::from_str(s.as_str()).map_err(|_| PlurkError::IOError(format!("{}", display)))
+        Plurk::from_toml_str(&s).map_err(|_| PlurkError::IOError(format!("{}", display)))
     }
 
     pub fn to_toml<P: AsRef<Path>>(&self, path: P) -> Result<(), PlurkError> {
         let display = path.as_ref().display().to_string();
 
         let mut file =
-            File::create(&path).map_err(|_| PlurkError::IOError(format!("{}", display)))?;
+            create_private_file(&path).map_err(|_| PlurkError::IOError(display.to_string()))?;
 
-        let s = toml::to_string(&self).map_err(|_| PlurkError::IOError(format!("{}", display)))?;
+        let s = self
+            .to_toml_string()
+            .map_err(|_| PlurkError::IOError(display.to_string()))?;
 
         file.write_all(s.as_bytes())
-            .map_err(|_| PlurkError::IOError(format!("{}", display)))?;
+            .map_err(|_| PlurkError::IOError(display.to_string()))?;
 
         Ok(())
     }
 
+    pub fn from_toml_str(s: &str) -> Result<Self, PlurkError> {
+        toml::from_str(s).map_err(|e| PlurkError::ParseError(e.to_string()))
+    }
+
+    pub fn to_toml_string(&self) -> Result<String, PlurkError> {
+        toml::to_string(&self).map_err(|e| PlurkError::ParseError(e.to_string()))
+    }
+
     pub fn has_token(&self) -> bool {
         match &self.oauth_token {
             Some(ot) if (ot.key.is_empty() || ot.secret.is_empty()) => false,
@@ -158,7 +254,21 @@ impl Plurk {
         }
     }
 
-    fn to_secret(&self) -> Secrets {
+    pub fn clear_token(&mut self) {
+        self.oauth_token = None;
+    }
+
+    /// Show how to export the acquired token, since env credentials are never
+    /// written back to a key file.
+    pub fn print_token_env(&self) {
+        if let Some(ot) = &self.oauth_token {
+            eprintln!("Acquired oauth token, export it to skip the pin next time:");
+            println!("export {}={}", ENV_TOKEN_KEY, ot.key);
+            println!("export {}={}", ENV_TOKEN_SECRET, ot.secret);
+        }
+    }
+
+    fn to_secret(&self) -> Secrets<'_> {
         match &self.oauth_token {
             Some(ot) if (ot.key.is_empty() || ot.secret.is_empty()) => {
                 Secrets::new(self.consumer.key.clone(), self.consumer.secret.clone())
@@ -171,14 +281,16 @@ impl Plurk {
 
     pub async fn request(&self, api: &str) -> Result<reqwest::Response, PlurkError> {
         let secrets = self.to_secret().clone();
-        Ok(reqwest::Client::new()
+        reqwest::Client::new()
             .oauth1(secrets)
             .post(Plurk::cmd(api))
             .send()
             .await
-            .map_err(|e| PlurkError::OauthError(e))?)
+            .map_err(PlurkError::OauthError)
     }
 
+    // `?Sized` mirrors the bound of `reqwest_oauth1::RequestBuilder::form`.
+    #[allow(clippy::needless_maybe_sized)]
     pub async fn request_query<T>(
         &self,
         api: &str,
@@ -188,62 +300,127 @@ impl Plurk {
         T: Serialize + ?Sized + Clone,
     {
         let secrets = self.to_secret().clone();
-        Ok(reqwest::Client::new()
+        reqwest::Client::new()
             .oauth1(secrets)
             .post(Plurk::cmd(api))
             .form(query)
             .send()
             .await
-            .map_err(|e| PlurkError::OauthError(e))?)
+            .map_err(PlurkError::OauthError)
     }
 
-    pub async fn acquire_plurk_key(&mut self) -> Result<(), PlurkError> {
-        let secrets = self.to_secret();
+    /// Call an API and decode the json body, turning `error_text` replies
+    /// into `PlurkError::ApiError`.
+    pub async fn call<T, Q>(&self, api: &str, query: &Q) -> Result<T, PlurkError>
+    where
+        T: DeserializeOwned,
+        Q: Serialize + Clone,
+    {
+        let resp = self.request_query(api, query).await?;
+        let status = resp.status();
+        let text = resp.text().await.map_err(PlurkError::ReqwestError)?;
+        if !status.is_success() {
+            return Err(match serde_json::from_str::<ApiErrorText>(&text) {
+                Ok(e) => PlurkError::ApiError(format!("{}: {}", api, e.error_text)),
+                Err(_) => PlurkError::ApiError(format!("{}: {} {}", api, status, text)),
+            });
+        }
+        serde_json::from_str(&text).map_err(|e| PlurkError::ParseError(format!("{}: {}", api, e)))
+    }
 
-        let endpoint_reqtoken = format!("{}{}", BASE_URL, REQUEST_TOKEN_URL);
+    /// Authorize with the PIN (`oob`) flow, asking the user on stdin.
+    pub async fn acquire_plurk_key(&mut self) -> Result<(), PlurkError> {
+        if !io::stdin().is_terminal() {
+            return Err(PlurkError::NotInteractive(String::from(
+                "no oauth token configured and stdin is not a terminal to ask for the pin",
+            )));
+        }
 
-        let client = reqwest::Client::new();
-        let resp = client
-            .oauth1(secrets)
-            .post(endpoint_reqtoken)
-            .query(&[("oauth_callback", "oob")])
-            .send()
-            .parse_oauth_token()
-            .await
-            .map_err(|e| PlurkError::OauthError(e))?;
+        // step 1. acquire request token
+        let req = self.request_token("oob").await?;
 
         // step 2. acquire user pin
-        let endpoint_authorize = format!(
-            "{}{}?oauth_token={}",
-            BASE_URL, AUTHORIZE_URL, resp.oauth_token
-        );
-        println!("Please access to: {}", endpoint_authorize);
+        println!("Please access to: {}", Plurk::authorize_url(&req));
         print!("Input pin:");
         io::stdout()
             .flush()
             .map_err(|_| PlurkError::IOError(String::from("IO error")))?;
 
         let mut user_input = String::new();
-        io::stdin()
+        let n = BufReader::new(tokio::io::stdin())
             .read_line(&mut user_input)
-            .expect("Failed to read the user input");
+            .await
+            .map_err(|e| PlurkError::IOError(e.to_string()))?;
+        if n == 0 {
+            return Err(PlurkError::NotInteractive(String::from(
+                "stdin closed before the pin was entered",
+            )));
+        }
         let pin = user_input.trim();
 
         // step 3. acquire access token
-        let secrets = self
-            .to_secret()
-            .token(resp.oauth_token, resp.oauth_token_secret);
+        self.access_token(req, pin).await
+    }
+
+    /// Authorize by redirecting the browser to a listener on 127.0.0.1, which
+    /// captures the `oauth_verifier` without any copy and paste.
+    pub async fn acquire_plurk_key_callback(
+        &mut self,
+        port: u16,
+        open_browser: bool,
+        timeout: Duration,
+    ) -> Result<(), PlurkError> {
+        let listener = CallbackListener::bind(port).await?;
+        let req = self.request_token(&listener.callback_url()?).await?;
+
+        let endpoint_authorize = Plurk::authorize_url(&req);
+        println!("Please access to: {}", endpoint_authorize);
+        if open_browser && webbrowser::open(&endpoint_authorize).is_err() {
+            eprintln!("Cannot open the browser, please open the url manually");
+        }
+        println!("Waiting for authorization...");
+
+        let verifier = tokio::time::timeout(timeout, listener.wait_verifier(&req.oauth_token))
+            .await
+            .map_err(|_| PlurkError::Timeout(String::from("authorization")))??;
+
+        self.access_token(req, &verifier).await
+    }
+
+    async fn request_token(&self, callback: &str) -> Result<TokenResponse, PlurkError> {
+        let secrets = Secrets::new(self.consumer.key.clone(), self.consumer.secret.clone());
+        let endpoint_reqtoken = format!("{}{}", BASE_URL, REQUEST_TOKEN_URL);
+
+        reqwest::Client::new()
+            .oauth1(secrets)
+            .post(endpoint_reqtoken)
+            .query(&[("oauth_callback", callback)])
+            .send()
+            .parse_oauth_token()
+            .await
+            .map_err(PlurkError::OauthError)
+    }
+
+    fn authorize_url(req: &TokenResponse) -> String {
+        format!(
+            "{}{}?oauth_token={}",
+            BASE_URL, AUTHORIZE_URL, req.oauth_token
+        )
+    }
+
+    async fn access_token(&mut self, req: TokenResponse, verifier: &str) -> Result<(), PlurkError> {
+        let secrets = Secrets::new(self.consumer.key.clone(), self.consumer.secret.clone())
+            .token(req.oauth_token, req.oauth_token_secret);
         let endpoint_acctoken = format!("{}{}", BASE_URL, ACCESS_TOKEN_URL);
 
-        let client = reqwest::Client::new();
-        let resp = client
+        let resp = reqwest::Client::new()
             .oauth1(secrets)
             .post(endpoint_acctoken)
-            .query(&[("oauth_verifier", pin)])
+            .query(&[("oauth_verifier", verifier)])
             .send()
             .parse_oauth_token()
             .await
-            .map_err(|e| PlurkError::OauthError(e))?;
+            .map_err(PlurkError::OauthError)?;
         let oauth_token = PlurkKeys {
             key: resp.oauth_token,
             secret: resp.oauth_token_secret,
@@ -283,3 +460,38 @@ impl fmt::Display for PlurkData {
         )
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn partial_users_take_defaults() {
+        let u: PlurkUser =
+            serde_json::from_str(r#"{"id": 5, "nick_name": "nick", "display_name": "Nick"}"#)
+                .unwrap();
+        assert_eq!((u.id, u.nick_name.as_str()), (5, "nick"));
+        assert_eq!((u.karma, u.premium, u.name_color), (0.0, false, None));
+    }
+
+    #[test]
+    fn responses_round_trip() {
+        let r: PlurkResponse = serde_json::from_str(
+            r#"{"id": 7, "plurk_id": 3, "user_id": 5, "posted": "Fri, 05 Jun 2009 23:07:13 GMT",
+                "qualifier": "says", "content": "hi", "content_raw": "hi", "lang": "en",
+                "last_edited": "Sat, 06 Jun 2009 01:00:00 GMT"}"#,
+        )
+        .unwrap();
+        let back: PlurkResponse =
+            serde_json::from_str(&serde_json::to_string(&r).unwrap()).unwrap();
+        assert_eq!(back.posted, r.posted);
+        assert_eq!(back.last_edited.map(|t| t.0), r.last_edited.map(|t| t.0));
+        assert_eq!((back.id, back.content_raw), (7, r.content_raw));
+    }
+
+    #[test]
+    fn users_need_an_id_and_nick() {
+        assert!(serde_json::from_str::<PlurkUser>(r#"{"nick_name": "nick"}"#).is_err());
+        assert!(serde_json::from_str::<PlurkUser>(r#"{"id": 5}"#).is_err());
+    }
+}
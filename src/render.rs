@@ -0,0 +1,474 @@
+// render.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Turn the html in `PlurkData::content` into terminal text.
+
+use crate::images::ImageMode;
+use crate::layout::{truncate, wrap};
+use crate::plurk::{PlurkData, PlurkResponse};
+use crate::theme::Theme;
+use crate::time::TimeFormat;
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use colored::Colorize;
+use regex::Regex;
+use std::collections::HashSet;
+use std::sync::LazyLock;
+use terminal_size::{terminal_size, Width};
+
+/// Indent of plurk content under its header line.
+const BODY_INDENT: &str = "  ";
+/// Indent of the continuation lines of a response.
+const HANGING_INDENT: &str = "    ";
+/// Used when the width is neither given nor detected, e.g. in a pipe.
+const DEFAULT_WIDTH: usize = 80;
+
+static ATTR_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"([a-zA-Z_-]+)\s*=\s*"([^"]*)""#).unwrap());
+
+/// Which form of the plurk content is printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ContentMode {
+    /// The plain text the author typed (`content_raw`)
+    Raw,
+    /// The html rendered with links, styles and emoticon codes
+    Rendered,
+    /// The html as returned by the API
+    Html,
+}
+
+/// Options shared by everything that prints plurks.
+#[derive(Clone, Debug)]
+pub struct RenderOptions {
+    pub content: ContentMode,
+    pub images: ImageMode,
+    /// Width of image previews in terminal columns.
+    pub image_width: u16,
+    /// Output width in columns, detected from the terminal if not set.
+    pub width: Option<u16>,
+    pub time: TimeFormat,
+    pub theme: Theme,
+    /// Users whose plurks and responses are hidden.
+    pub blocked: HashSet<u64>,
+}
+
+impl RenderOptions {
+    /// Pick the content of a plurk or response to print.
+    pub fn content(&self, content_raw: &str, content: &str) -> String {
+        match self.content {
+            ContentMode::Raw => content_raw.to_string(),
+            ContentMode::Html => content.to_string(),
+            ContentMode::Rendered => to_ansi(&parse_html(content), colorize()),
+        }
+    }
+
+    /// Whether a plurk is by, or replurked by, a blocked user.
+    pub fn hides(&self, p: &PlurkData) -> bool {
+        self.blocked.contains(&p.owner_id)
+            || p.replurker_id.is_some_and(|id| self.blocked.contains(&id))
+    }
+
+    /// Format the time a plurk or response was posted.
+    pub fn timestamp(&self, t: &DateTime<FixedOffset>) -> String {
+        self.time.format(t)
+    }
+
+    pub fn width(&self) -> usize {
+        match (self.width, terminal_size()) {
+            (Some(w), _) => usize::from(w.max(1)),
+            (None, Some((Width(w), _))) => usize::from(w),
+            (None, None) => DEFAULT_WIDTH,
+        }
+    }
+
+    /// Line between plurks, as wide as the output.
+    pub fn separator(&self) -> String {
+        self.theme
+            .separator
+            .paint(&"=".repeat(self.width()))
+            .to_string()
+    }
+
+    /// Line between a plurk and its responses.
+    pub fn rule(&self) -> String {
+        self.theme.separator.paint(" -------").to_string()
+    }
+
+    /// Time, owner and qualifier of a plurk.
+    pub fn header(&self, p: &PlurkData, name: &str, name_color: Option<&str>) -> String {
+        format!(
+            "{} {} {}",
+            self.theme.timestamp.paint(&self.timestamp(&p.posted)),
+            self.theme.name(name, name_color),
+            self.theme.qualifier.paint(&p.qualifier)
+        )
+    }
+
+    /// A response with its id on one wrapped entry, indented by `depth`
+    /// levels of a thread.
+    pub fn response(&self, r: &PlurkResponse, name: &str, depth: usize) -> String {
+        let indent = "  ".repeat(depth);
+        wrap(
+            &format!(
+                "{} {} {} {} {}",
+                format!("#{}", r.id).dimmed(),
+                self.theme.timestamp.paint(&self.timestamp(&r.posted)),
+                self.theme.response.paint(name),
+                self.theme.qualifier.paint(&r.qualifier),
+                self.content(&r.content_raw, &r.content)
+            ),
+            self.width(),
+            &indent,
+            &format!("{}{}", indent, HANGING_INDENT),
+        )
+    }
+
+    /// Content printed under a header, wrapped and indented.
+    pub fn body(&self, text: &str) -> String {
+        wrap(text, self.width(), BODY_INDENT, BODY_INDENT)
+    }
+
+    /// A single entry wrapped with its continuation lines indented.
+    pub fn hanging(&self, text: &str) -> String {
+        wrap(text, self.width(), "", HANGING_INDENT)
+    }
+
+    /// Cut a compact line to fit in one row.
+    pub fn line(&self, text: &str) -> String {
+        truncate(text, self.width())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TextStyle {
+    pub bold: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub strike: bool,
+    pub code: bool,
+    pub mention: bool,
+}
+
+/// A run of text sharing one style and link target.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Segment {
+    pub text: String,
+    pub style: TextStyle,
+    pub link: Option<String>,
+}
+
+/// Nesting depth of each style tag, so `<b><b>x</b>y</b>` stays bold.
+#[derive(Default)]
+struct Depth {
+    bold: u8,
+    italic: u8,
+    underline: u8,
+    strike: u8,
+    code: u8,
+}
+
+pub fn colorize() -> bool {
+    colored::control::SHOULD_COLORIZE.should_colorize()
+}
+
+pub fn parse_html(html: &str) -> Vec<Segment> {
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut depth = Depth::default();
+    let mut links: Vec<Option<String>> = Vec::new();
+    let mut rest = html;
+
+    let push =
+        |segments: &mut Vec<Segment>, text: String, style: TextStyle, link: Option<String>| {
+            if text.is_empty() {
+                return;
+            }
+            match segments.last_mut() {
+                Some(last) if last.style == style && last.link == link => last.text.push_str(&text),
+                _ => segments.push(Segment { text, style, link }),
+            }
+        };
+
+    while !rest.is_empty() {
+        let link = links.iter().rev().flatten().next().cloned();
+        let style = TextStyle {
+            bold: depth.bold > 0,
+            italic: depth.italic > 0,
+            underline: depth.underline > 0,
+            strike: depth.strike > 0,
+            code: depth.code > 0,
+            mention: false,
+        };
+
+        let Some(tag) = rest.strip_prefix('<') else {
+            let end = rest.find('<').unwrap_or(rest.len());
+            let text = decode_entities(&rest[..end]);
+            let style = TextStyle {
+                mention: link.as_deref().is_some_and(is_user_link) && text.starts_with('@'),
+                ..style
+            };
+            push(&mut segments, text, style, link);
+            rest = &rest[end..];
+            continue;
+        };
+        let Some(end) = tag.find('>') else {
+            push(&mut segments, decode_entities(rest), style, link);
+            break;
+        };
+        rest = &tag[end + 1..];
+        let tag = tag[..end].trim_end_matches('/').trim();
+        let closing = tag.starts_with('/');
+        let tag = tag.trim_start_matches('/');
+        let name = tag
+            .split(|c: char| c.is_whitespace())
+            .next()
+            .unwrap_or("")
+            .to_ascii_lowercase();
+        let attr = |key: &str| {
+            ATTR_RE
+                .captures_iter(tag)
+                .find(|c| c[1].eq_ignore_ascii_case(key))
+                .map(|c| decode_entities(&c[2]))
+        };
+
+        let counter = match name.as_str() {
+            "b" | "strong" => Some(&mut depth.bold),
+            "i" | "em" => Some(&mut depth.italic),
+            "u" => Some(&mut depth.underline),
+            "s" | "del" | "strike" => Some(&mut depth.strike),
+            "code" | "pre" => Some(&mut depth.code),
+            _ => None,
+        };
+        if let Some(counter) = counter {
+            if closing {
+                *counter = counter.saturating_sub(1);
+            } else {
+                *counter += 1;
+            }
+        }
+
+        let breaks_line = match name.as_str() {
+            "br" | "pre" => true,
+            "p" | "div" => closing,
+            _ => false,
+        };
+        if breaks_line {
+            push(
+                &mut segments,
+                String::from("\n"),
+                TextStyle::default(),
+                None,
+            );
+        }
+
+        match name.as_str() {
+            "a" if closing => {
+                links.pop();
+            }
+            "a" => links.push(attr("href")),
+            "img" => {
+                let class = attr("class").unwrap_or_default();
+                if class.split_whitespace().any(|c| c.starts_with("emoticon")) {
+                    let code = attr("alt").unwrap_or_else(|| String::from("(emoticon)"));
+                    push(&mut segments, code, style, link);
+                } else {
+                    let link = link.or_else(|| attr("src"));
+                    push(&mut segments, String::from("[img]"), style, link);
+                }
+            }
+            _ => {}
+        }
+    }
+    segments
+}
+
+/// Links to a profile page, e.g. `https://www.plurk.com/nick`.
+fn is_user_link(href: &str) -> bool {
+    match href.strip_prefix("https://www.plurk.com/") {
+        Some(path) => !path.is_empty() && !path.contains('/'),
+        None => false,
+    }
+}
+
+/// Control characters other than newlines and tabs would reach the terminal
+/// as escape sequences, they are replaced with U+FFFD.
+fn printable(c: char) -> char {
+    if c.is_control() && c != '\n' && c != '\t' {
+        char::REPLACEMENT_CHARACTER
+    } else {
+        c
+    }
+}
+
+pub fn decode_entities(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('&') {
+        out.extend(rest[..start].chars().map(printable));
+        rest = &rest[start..];
+        let decoded = rest.find(';').filter(|&end| end <= 10).and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                "nbsp" => Some(' '),
+                _ => match entity.strip_prefix('#') {
+                    Some(hex) if hex.starts_with(['x', 'X']) => u32::from_str_radix(&hex[1..], 16)
+                        .ok()
+                        .and_then(char::from_u32),
+                    Some(dec) => dec.parse().ok().and_then(char::from_u32),
+                    None => None,
+                },
+            };
+            c.map(|c| (c, end))
+        });
+        match decoded {
+            Some((c, end)) => {
+                out.push(printable(c));
+                rest = &rest[end + 1..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.extend(rest.chars().map(printable));
+    out
+}
+
+/// Print segments with SGR styles and OSC 8 hyperlinks, or as plain text
+/// with links spelled out when colors are off. Links holding control
+/// characters are left out, they could end the OSC 8 sequence early.
+pub fn to_ansi(segments: &[Segment], color: bool) -> String {
+    let mut out = String::new();
+    for seg in segments {
+        let link = seg
+            .link
+            .as_ref()
+            .filter(|l| !l.chars().any(char::is_control));
+        if !color {
+            out.push_str(&seg.text);
+            if let Some(link) = link {
+                if *link != seg.text && !seg.style.mention {
+                    out.push_str(&format!(" <{}>", link));
+                }
+            }
+            continue;
+        }
+
+        let mut codes = Vec::new();
+        let s = seg.style;
+        if s.bold || s.mention {
+            codes.push("1");
+        }
+        if s.italic {
+            codes.push("3");
+        }
+        if s.underline || (link.is_some() && !s.mention) {
+            codes.push("4");
+        }
+        if s.strike {
+            codes.push("9");
+        }
+        if s.code {
+            codes.push("7");
+        }
+        if s.mention {
+            codes.push("94");
+        }
+
+        if let Some(link) = link {
+            out.push_str(&format!("\x1b]8;;{}\x1b\\", link));
+        }
+        if codes.is_empty() {
+            out.push_str(&seg.text);
+        } else {
+            out.push_str(&format!("\x1b[{}m{}\x1b[0m", codes.join(";"), seg.text));
+        }
+        if link.is_some() {
+            out.push_str("\x1b]8;;\x1b\\");
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text(segments: &[Segment]) -> String {
+        segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn entities() {
+        assert_eq!(decode_entities("a &amp; b &lt;c&gt;"), "a & b <c>");
+        assert_eq!(decode_entities("&quot;&apos;&nbsp;"), "\"' ");
+        assert_eq!(decode_entities("&#22103;&#x6D6A;"), "噗浪");
+        // Unknown or unterminated entities are kept as they are.
+        assert_eq!(decode_entities("&bogus; & &amp"), "&bogus; & &amp");
+    }
+
+    #[test]
+    fn control_characters_are_replaced() {
+        assert_eq!(decode_entities("&#27;[2J"), "\u{fffd}[2J");
+        assert_eq!(decode_entities("&#x9b;2J&#7;"), "\u{fffd}2J\u{fffd}");
+        assert_eq!(decode_entities("a\x1b[31mb"), "a\u{fffd}[31mb");
+        assert_eq!(decode_entities("line&#10;\tnext"), "line\n\tnext");
+    }
+
+    #[test]
+    fn links_with_control_characters_are_dropped() {
+        let segs = [Segment {
+            text: String::from("click"),
+            style: TextStyle::default(),
+            link: Some(String::from("https://example.com/\x1b]8;;evil")),
+        }];
+        for color in [true, false] {
+            let out = to_ansi(&segs, color);
+            assert!(!out.contains("example.com"), "{:?}", out);
+            assert!(out.contains("click"));
+        }
+    }
+
+    #[test]
+    fn nested_styles() {
+        let segs = parse_html("<b>bold <i>both</i></b> plain");
+        assert_eq!(text(&segs), "bold both plain");
+        assert!(segs[0].style.bold && !segs[0].style.italic);
+        assert!(segs[1].style.bold && segs[1].style.italic);
+        assert_eq!(segs[2].style, TextStyle::default());
+    }
+
+    #[test]
+    fn links_and_mentions() {
+        let segs = parse_html(
+            "<a href=\"https://www.plurk.com/nick\" class=\"ex_link\">@nick</a>: see \
+             <a href=\"https://example.com/?a=1&amp;b=2\">this</a>",
+        );
+        assert!(segs[0].style.mention);
+        assert_eq!(segs[0].link.as_deref(), Some("https://www.plurk.com/nick"));
+        let link = segs.iter().find(|s| s.text == "this").unwrap();
+        assert!(!link.style.mention);
+        assert_eq!(link.link.as_deref(), Some("https://example.com/?a=1&b=2"));
+    }
+
+    #[test]
+    fn images_and_emoticons() {
+        let segs = parse_html(
+            "<img src=\"https://s.plurk.com/e.gif\" class=\"emoticon_my\" alt=\"(LOL)\">\
+             <br><img src=\"https://images.plurk.com/x.jpg\">",
+        );
+        assert_eq!(text(&segs), "(LOL)\n[img]");
+        let img = segs.iter().find(|s| s.text == "[img]").unwrap();
+        assert_eq!(img.link.as_deref(), Some("https://images.plurk.com/x.jpg"));
+    }
+
+    #[test]
+    fn unclosed_tag_is_text() {
+        assert_eq!(text(&parse_html("1 < 2")), "1 < 2");
+    }
+}
@@ -0,0 +1,140 @@
+// search.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Search plurks and users on plurk or in the local archive.
+
+use crate::app::{plurk_line, print_plurk, user_line};
+use crate::archive::{record, Archive, Hit, SearchFilter};
+use crate::error::PlurkError;
+use crate::plurk::{Plurk, PlurkUser};
+use crate::render::RenderOptions;
+use crate::utils::base36_encode;
+
+/// Print the public plurks matching `terms`, following the pages until
+/// they are exhausted or `limit` plurks were shown.
+pub async fn print_remote(
+    plurk: &Plurk,
+    terms: &[String],
+    limit: Option<u64>,
+    verbose: bool,
+    archive: Option<&Archive>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let query = terms.join(" ");
+    let mut offset = None;
+    let mut shown = 0;
+    loop {
+        let page = plurk.plurk_search(&query, offset).await?;
+        record(archive, &page.plurks, [], page.plurk_users.values());
+        for p in &page.plurks {
+            if limit.is_some_and(|n| shown >= n) {
+                return Ok(());
+            }
+            let user = match page.plurk_users.get(&p.owner_id) {
+                Some(user) => user.clone(),
+                None => PlurkUser {
+                    id: p.owner_id,
+                    display_name: p.owner_id.to_string(),
+                    ..Default::default()
+                },
+            };
+            print_plurk(p, &user, verbose, opts).await;
+            shown += 1;
+        }
+        if !page.has_more || page.plurks.is_empty() || page.last_offset == offset {
+            break;
+        }
+        offset = page.last_offset;
+    }
+    if shown == 0 {
+        eprintln!("No matches");
+    }
+    Ok(())
+}
+
+/// Print the users matching `name`.
+pub async fn print_users(
+    plurk: &Plurk,
+    name: &str,
+    limit: u64,
+    archive: Option<&Archive>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let mut offset = 0;
+    while offset < limit {
+        let page = plurk.user_search(name, offset).await?;
+        if page.users.is_empty() {
+            break;
+        }
+        record(archive, [], [], &page.users);
+        for u in page.users.iter().take((limit - offset) as usize) {
+            println!("{}", user_line(u, opts));
+        }
+        offset += page.users.len() as u64;
+        if page.counts.is_some_and(|n| offset >= n) {
+            break;
+        }
+    }
+    if offset == 0 {
+        eprintln!("No users found");
+    }
+    Ok(())
+}
+
+/// Print the archived plurks and responses matching `terms`, the same way
+/// the timeline does.
+pub fn print_local(
+    archive: &Archive,
+    terms: &[String],
+    filter: &SearchFilter,
+    verbose: bool,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let hits = archive.search(terms, filter)?;
+    if hits.is_empty() {
+        eprintln!("No matches in {}", archive.path().display());
+    }
+    for hit in hits {
+        match hit {
+            Hit::Plurk {
+                plurk: p,
+                name,
+                name_color,
+            } => {
+                if verbose {
+                    println!(
+                        "Plurk ==> https://www.plurk.com/p/{}",
+                        base36_encode(p.plurk_id)
+                    );
+                    println!("{}", opts.header(&p, &name, name_color.as_deref()));
+                    println!("{}", opts.body(&opts.content(&p.content_raw, &p.content)));
+                    println!("{}", opts.separator());
+                } else {
+                    let line = plurk_line(&p, &name, name_color.as_deref(), opts);
+                    println!("{}", opts.line(&line));
+                }
+            }
+            Hit::Response { response: r, name } => {
+                if verbose {
+                    println!(
+                        "Response ==> https://www.plurk.com/p/{}",
+                        base36_encode(r.plurk_id)
+                    );
+                    println!("{}", opts.response(&r, &name, 0));
+                    println!("{}", opts.separator());
+                } else {
+                    let line = format!(
+                        "↳ {} {} {} {}",
+                        opts.theme.timestamp.paint(&opts.timestamp(&r.posted)),
+                        opts.theme.response.paint(&name),
+                        opts.theme.qualifier.paint(&r.qualifier),
+                        opts.content(&r.content_raw, &r.content)
+                            .replace('\n', "   ")
+                    );
+                    println!("{}", opts.line(&line));
+                }
+            }
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,114 @@
+// secret.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+
+use crate::error::PlurkError;
+use crate::plurk::Plurk;
+use crate::utils::{create_private_file, warn_if_exposed};
+use age::secrecy::SecretString;
+use clap::ValueEnum;
+use keyring::Entry;
+use std::env;
+use std::fs;
+use std::io::prelude::*;
+use std::path::Path;
+
+const KEYRING_SERVICE: &str = "plurk-cli";
+const PASSPHRASE_ENV: &str = "PLURK_PASSPHRASE";
+
+/// Where the consumer and oauth token secrets are kept.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum SecretBackend {
+    /// Plaintext TOML key file (created with 0600)
+    Toml,
+    /// OS keyring: Secret Service, macOS Keychain or Windows Credential Manager
+    Keyring,
+    /// Key file encrypted with an age passphrase
+    Age,
+}
+
+impl SecretBackend {
+    /// Load the keys. `path` is the key file, or the keyring account name
+    /// for the keyring backend.
+    pub async fn load(&self, path: &str) -> Result<Plurk, PlurkError> {
+        match self {
+            Self::Toml => Plurk::from_toml(path),
+            Self::Keyring => {
+                let s = with_keyring(path, |entry| entry.get_password()).await?;
+                Plurk::from_toml_str(&s)
+            }
+            Self::Age => {
+                let data = fs::read(path).map_err(|_| PlurkError::IOError(path.to_string()))?;
+                warn_if_exposed(path);
+                let identity = age::scrypt::Identity::new(passphrase(false)?);
+                let plain = age::decrypt(&identity, &data)
+                    .map_err(|e| PlurkError::SecretError(e.to_string()))?;
+                let s =
+                    String::from_utf8(plain).map_err(|e| PlurkError::ParseError(e.to_string()))?;
+                Plurk::from_toml_str(&s)
+            }
+        }
+    }
+
+    pub async fn exists(&self, path: &str) -> bool {
+        match self {
+            Self::Toml | Self::Age => Path::new(path).exists(),
+            Self::Keyring => with_keyring(path, |entry| entry.get_password())
+                .await
+                .is_ok(),
+        }
+    }
+
+    pub async fn store(&self, plurk: &Plurk, path: &str) -> Result<(), PlurkError> {
+        match self {
+            Self::Toml => plurk.to_toml(path),
+            Self::Keyring => {
+                let s = plurk.to_toml_string()?;
+                with_keyring(path, move |entry| entry.set_password(&s)).await
+            }
+            Self::Age => {
+                let recipient = age::scrypt::Recipient::new(passphrase(true)?);
+                let s = plurk.to_toml_string()?;
+                let data = age::encrypt(&recipient, s.as_bytes())
+                    .map_err(|e| PlurkError::SecretError(e.to_string()))?;
+                let mut file =
+                    create_private_file(path).map_err(|_| PlurkError::IOError(path.to_string()))?;
+                file.write_all(&data)
+                    .map_err(|_| PlurkError::IOError(path.to_string()))
+            }
+        }
+    }
+}
+
+/// Run a keyring call on the blocking pool, the platform stores may block.
+async fn with_keyring<T, F>(account: &str, call: F) -> Result<T, PlurkError>
+where
+    T: Send + 'static,
+    F: FnOnce(&Entry) -> keyring::Result<T> + Send + 'static,
+{
+    let entry =
+        Entry::new(KEYRING_SERVICE, account).map_err(|e| PlurkError::SecretError(e.to_string()))?;
+    tokio::task::spawn_blocking(move || call(&entry))
+        .await
+        .map_err(|e| PlurkError::SecretError(e.to_string()))?
+        .map_err(|e| PlurkError::SecretError(e.to_string()))
+}
+
+/// Read the key file passphrase from `PLURK_PASSPHRASE`, or prompt for it.
+fn passphrase(confirm: bool) -> Result<SecretString, PlurkError> {
+    if let Ok(p) = env::var(PASSPHRASE_ENV) {
+        return Ok(SecretString::from(p));
+    }
+    let p = rpassword::prompt_password("Key file passphrase: ")
+        .map_err(|e| PlurkError::IOError(e.to_string()))?;
+    if confirm {
+        let again = rpassword::prompt_password("Confirm passphrase: ")
+            .map_err(|e| PlurkError::IOError(e.to_string()))?;
+        if p != again {
+            return Err(PlurkError::SecretError(String::from(
+                "passphrases do not match",
+            )));
+        }
+    }
+    Ok(SecretString::from(p))
+}
@@ -0,0 +1,439 @@
+// shell.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+
+use crate::app::{config_dir, plurk_line};
+use crate::comet::{CometContentUnit, CometFeed};
+use crate::error::PlurkError;
+use crate::plurk::{Plurk, PlurkUser};
+use crate::render::RenderOptions;
+use crate::thread::thread_order;
+use crate::utils::{base36_encode, parse_plurk_id};
+use colored::Colorize;
+use rustyline::completion::{Completer, Pair};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::DefaultHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, ExternalPrinter, Helper};
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+
+const COMMANDS: &[&str] = &[
+    "tl", "show", "r", "p", "like", "unlike", "replurk", "mute", "read", "me", "help", "quit",
+];
+/// Commands whose first argument is a plurk shown on screen.
+const ID_COMMANDS: &[&str] = &["show", "r", "like", "unlike", "replurk", "mute", "read"];
+
+const HELP: &str = "\
+tl [limit]          list the timeline, numbering every plurk
+show <n|id>         show a plurk and its responses
+r <n|id> <text>     respond to a plurk
+p <text>            post a new plurk
+like|unlike <n|id>  like or unlike a plurk
+replurk <n|id>      replurk a plurk
+mute <n|id>         mute a plurk
+read <n|id>         mark a plurk as read
+me                  show your profile
+quit                leave the shell
+<n> is the number shown in brackets, <id> a plurk slug, url or id:<decimal id>.";
+
+/// What the completer and the comet listener share with the repl.
+#[derive(Default)]
+struct Shared {
+    /// Plurk ids in the order they were shown, `[1]` is the first.
+    items: Vec<u64>,
+    nicks: Vec<String>,
+    names: HashMap<u64, String>,
+}
+
+impl Shared {
+    /// Remember a shown plurk and return its number.
+    fn push(&mut self, plurk_id: u64) -> usize {
+        match self.items.iter().position(|&id| id == plurk_id) {
+            Some(i) => i + 1,
+            None => {
+                self.items.push(plurk_id);
+                self.items.len()
+            }
+        }
+    }
+
+    /// A number refers to a plurk shown on screen, anything else is parsed
+    /// as a plurk slug, url or `id:<id>`.
+    fn resolve(&self, value: &str) -> Option<u64> {
+        match value.parse::<usize>() {
+            Ok(n) => n.checked_sub(1).and_then(|i| self.items.get(i)).copied(),
+            Err(_) => parse_plurk_id(value),
+        }
+    }
+
+    fn name(&self, user_id: u64) -> String {
+        match self.names.get(&user_id) {
+            Some(n) => n.clone(),
+            None => user_id.to_string(),
+        }
+    }
+
+    fn add_users<'a, I: IntoIterator<Item = &'a PlurkUser>>(&mut self, users: I) {
+        for u in users {
+            self.names.insert(u.id, u.display_name.clone());
+        }
+    }
+}
+
+type State = Arc<Mutex<Shared>>;
+
+struct ShellHelper {
+    state: State,
+}
+
+impl Helper for ShellHelper {}
+impl Highlighter for ShellHelper {}
+impl Validator for ShellHelper {}
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
+
+impl Completer for ShellHelper {
+    type Candidate = Pair;
+
+    fn complete(
+        &self,
+        line: &str,
+        pos: usize,
+        _ctx: &Context<'_>,
+    ) -> rustyline::Result<(usize, Vec<Pair>)> {
+        let head = &line[..pos];
+        let start = head.rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &head[start..];
+        let args: Vec<&str> = head[..start].split_whitespace().collect();
+        let state = self.state.lock().unwrap();
+
+        let candidates: Vec<String> = if args.is_empty() {
+            COMMANDS.iter().map(|c| c.to_string()).collect()
+        } else if word.starts_with('@') {
+            state.nicks.iter().map(|n| format!("@{}", n)).collect()
+        } else if args.len() == 1 && ID_COMMANDS.contains(&args[0]) {
+            (1..=state.items.len())
+                .map(|n| n.to_string())
+                .chain(state.items.iter().map(|id| base36_encode(*id)))
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        let pairs = candidates
+            .into_iter()
+            .filter(|c| c.starts_with(word))
+            .map(|c| Pair {
+                display: c.clone(),
+                replacement: c,
+            })
+            .collect();
+        Ok((start, pairs))
+    }
+}
+
+fn history_file() -> PathBuf {
+    match dirs::data_dir() {
+        Some(dir) => dir.join("plurk-cli").join("shell_history"),
+        None => config_dir().join("shell_history"),
+    }
+}
+
+pub async fn run_shell(plurk: Plurk, opts: &RenderOptions) -> Result<(), PlurkError> {
+    let state: State = Arc::new(Mutex::new(Shared::default()));
+
+    let mut editor: Editor<ShellHelper, DefaultHistory> =
+        Editor::new().map_err(|e| PlurkError::IOError(e.to_string()))?;
+    editor.set_helper(Some(ShellHelper {
+        state: state.clone(),
+    }));
+    let history = history_file();
+    let _ = editor.load_history(&history);
+
+    // Completion data is nice to have, the shell works without it.
+    if let Ok(friends) = plurk.get_completion().await {
+        state.lock().unwrap().nicks = friends.into_values().map(|u| u.nick_name).collect();
+    }
+
+    let listener = match editor.create_external_printer() {
+        Ok(printer) => Some(tokio::spawn(comet_printer(
+            plurk.clone(),
+            state.clone(),
+            printer,
+            opts.clone(),
+        ))),
+        Err(_) => None,
+    };
+
+    println!("Plurk shell, type `help` for commands");
+    let mut editor = Some(editor);
+    loop {
+        let mut ed = editor.take().unwrap();
+        let (ed, line) = tokio::task::spawn_blocking(move || {
+            let line = ed.readline("plurk> ");
+            (ed, line)
+        })
+        .await
+        .map_err(|e| PlurkError::IOError(e.to_string()))?;
+        editor = Some(ed);
+
+        let line = match line {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(PlurkError::IOError(e.to_string())),
+        };
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        if let Some(ed) = editor.as_mut() {
+            let _ = ed.add_history_entry(line);
+        }
+        if matches!(line, "quit" | "exit" | "q") {
+            break;
+        }
+        if let Err(e) = run_command(&plurk, &state, line, opts).await {
+            eprintln!("{}", e.to_string().red());
+        }
+    }
+
+    if let Some(listener) = listener {
+        listener.abort();
+    }
+    if let Some(mut ed) = editor {
+        if let Some(dir) = history.parent() {
+            let _ = std::fs::create_dir_all(dir);
+        }
+        let _ = ed.save_history(&history);
+    }
+    Ok(())
+}
+
+async fn run_command(
+    plurk: &Plurk,
+    state: &State,
+    line: &str,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let (cmd, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let rest = rest.trim();
+    let (arg, text) = rest.split_once(char::is_whitespace).unwrap_or((rest, ""));
+    let text = text.trim();
+
+    let target = || -> Result<u64, PlurkError> {
+        state
+            .lock()
+            .unwrap()
+            .resolve(arg)
+            .ok_or_else(|| PlurkError::InvalidUrl(format!("no such plurk: {}", arg)))
+    };
+
+    match cmd {
+        "help" | "?" => println!("{}", HELP),
+        "tl" | "timeline" => {
+            let limit = arg.parse().unwrap_or(20);
+            let time = chrono::offset::Utc::now() - chrono::Duration::days(1);
+            let time = time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+            let body = plurk.get_plurks(&time, limit).await?;
+            let mut state = state.lock().unwrap();
+            state.items.clear();
+            state.add_users(body.plurk_users.unwrap_or_default().values());
+            for p in body.plurks.unwrap_or_default() {
+                if opts.hides(&p) {
+                    continue;
+                }
+                let n = state.push(p.plurk_id);
+                let line = format!(
+                    "[{}] {}",
+                    n,
+                    plurk_line(&p, &state.name(p.owner_id), None, opts)
+                );
+                println!("{}", opts.line(&line));
+            }
+        }
+        "show" => {
+            let plurk_id = target()?;
+            let thread = plurk.get_plurk(plurk_id).await?;
+            let mut responses = plurk.get_responses(plurk_id, 0).await?;
+            responses
+                .responses
+                .retain(|r| !opts.blocked.contains(&r.user_id));
+            let mut state = state.lock().unwrap();
+            state.add_users([&thread.user]);
+            state.add_users(responses.friends.values());
+            let n = state.push(plurk_id);
+            println!(
+                "[{}] https://www.plurk.com/p/{}",
+                n,
+                base36_encode(plurk_id)
+            );
+            println!(
+                "{}",
+                opts.hanging(&plurk_line(
+                    &thread.plurk,
+                    &thread.user.display_name,
+                    thread.user.name_color.as_deref(),
+                    opts,
+                ))
+            );
+            let mut nicks: HashMap<u64, String> = responses
+                .friends
+                .iter()
+                .map(|(id, u)| (*id, u.nick_name.clone()))
+                .collect();
+            nicks.insert(thread.user.id, thread.user.nick_name.clone());
+            for (i, depth) in thread_order(&responses.responses, &nicks, false) {
+                let r = &responses.responses[i];
+                let line = format!(
+                    "    {}{} {} {}",
+                    "  ".repeat(depth),
+                    opts.theme.response.paint(&state.name(r.user_id)),
+                    opts.theme.qualifier.paint(&r.qualifier),
+                    opts.content(&r.content_raw, &r.content)
+                );
+                println!("{}", opts.hanging(&line));
+            }
+        }
+        "r" => {
+            if text.is_empty() {
+                return Err(PlurkError::ParseError(String::from(
+                    "usage: r <n|id> <text>",
+                )));
+            }
+            plurk.response_add(target()?, text, ":").await?;
+            println!("Responded");
+        }
+        "p" => {
+            if rest.is_empty() {
+                return Err(PlurkError::ParseError(String::from("usage: p <text>")));
+            }
+            let p = plurk.plurk_add(rest, ":", None).await?;
+            let n = state.lock().unwrap().push(p.plurk_id);
+            println!(
+                "[{}] Posted https://www.plurk.com/p/{}",
+                n,
+                base36_encode(p.plurk_id)
+            );
+        }
+        "like" => plurk.timeline_ids("favoritePlurks", &[target()?]).await?,
+        "unlike" => plurk.timeline_ids("unfavoritePlurks", &[target()?]).await?,
+        "replurk" => plurk.timeline_ids("replurk", &[target()?]).await?,
+        "mute" => plurk.timeline_ids("mutePlurks", &[target()?]).await?,
+        "read" => plurk.timeline_ids("markAsRead", &[target()?]).await?,
+        "me" => {
+            let me: PlurkUser = plurk
+                .request("/APP/Users/me")
+                .await?
+                .json()
+                .await
+                .map_err(|e| PlurkError::ParseError(e.to_string()))?;
+            println!("{}", me);
+        }
+        _ => {
+            return Err(PlurkError::ParseError(format!(
+                "unknown command `{}`, type `help`",
+                cmd
+            )))
+        }
+    }
+    Ok(())
+}
+
+/// Print comet events above the prompt, numbering new plurks like `tl` does.
+async fn comet_printer<P: ExternalPrinter>(
+    plurk: Plurk,
+    state: State,
+    mut printer: P,
+    opts: RenderOptions,
+) {
+    let mut feed = CometFeed::new(plurk.clone());
+    loop {
+        let datas = match feed.next().await {
+            Ok(datas) => datas,
+            Err(e) => {
+                let _ = printer.print(format!("comet unavailable: {}", e));
+                return;
+            }
+        };
+
+        for data in datas.into_iter().filter(|d| !d.hidden(&opts)) {
+            let msg = match data {
+                CometContentUnit::Plurk(p) => {
+                    let known = state.lock().unwrap().names.contains_key(&p.owner_id);
+                    if !known {
+                        if let Ok(u) = plurk.get_public_profile(p.owner_id).await {
+                            state.lock().unwrap().add_users([&u]);
+                        }
+                    }
+                    let mut state = state.lock().unwrap();
+                    let n = state.push(p.plurk_id);
+                    opts.line(&format!(
+                        "[{}] {}",
+                        n,
+                        plurk_line(&p, &state.name(p.owner_id), None, &opts)
+                    ))
+                }
+                CometContentUnit::Response {
+                    plurk_id,
+                    response,
+                    user,
+                    ..
+                } => {
+                    let mut state = state.lock().unwrap();
+                    state.add_users(user.values());
+                    let n = state.push(plurk_id);
+                    opts.line(&format!(
+                        "[{}] {} {} {}",
+                        n,
+                        opts.theme.response.paint(&state.name(response.user_id)),
+                        opts.theme.qualifier.paint(&response.qualifier),
+                        opts.content(&response.content_raw, &response.content)
+                            .replace("\n", "   ")
+                    ))
+                }
+                CometContentUnit::Notification { counts } => counts.summary(),
+            };
+            if printer.print(msg).is_err() {
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolve_item_numbers() {
+        let mut shared = Shared::default();
+        assert_eq!(shared.push(100), 1);
+        assert_eq!(shared.push(200), 2);
+        assert_eq!(shared.push(100), 1);
+        assert_eq!(shared.resolve("1"), Some(100));
+        assert_eq!(shared.resolve("2"), Some(200));
+    }
+
+    #[test]
+    fn resolve_out_of_range_is_an_error() {
+        let mut shared = Shared::default();
+        shared.push(100);
+        assert_eq!(shared.resolve("0"), None);
+        assert_eq!(shared.resolve("2"), None);
+        assert_eq!(shared.resolve("99999999999999999999"), None);
+    }
+
+    #[test]
+    fn resolve_slugs_and_urls() {
+        let shared = Shared::default();
+        assert_eq!(shared.resolve("id:42"), Some(42));
+        assert_eq!(shared.resolve("a"), Some(10));
+        assert_eq!(shared.resolve("https://www.plurk.com/p/a"), Some(10));
+    }
+}
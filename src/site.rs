@@ -0,0 +1,319 @@
+// site.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Static html site browsing an export offline.
+
+use crate::error::PlurkError;
+use crate::export::{read_jsonl, MEDIA_DIR, MEDIA_FILE, PLURKS_FILE, RESPONSES_FILE, USERS_FILE};
+use crate::images::fetch;
+use crate::plurk::{PlurkData, PlurkResponse, PlurkUser};
+use crate::thread::thread_order;
+use crate::time::TimeFormat;
+use crate::utils::base36_encode;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::Path;
+
+pub const SITE_DIR: &str = "html";
+const AVATAR_DIR: &str = "avatars";
+const IMAGE_PREFIX: &str = "https://images.plurk.com/";
+const DATE_FORMAT: &str = "%Y-%m-%d %H:%M";
+
+const STYLE: &str =
+    "body { font-family: sans-serif; max-width: 48em; margin: 0 auto; padding: 1em; }
+a { color: #2a6fb0; }
+nav { margin-bottom: 1em; }
+.post { display: flex; gap: .6em; margin: .8em 0; }
+.avatar { width: 32px; height: 32px; border-radius: 4px; flex: none; }
+.meta { color: #777; font-size: .85em; }
+.name { font-weight: bold; }
+.qualifier { background: #eee; padding: 0 .3em; border-radius: 3px; }
+.content img { max-width: 100%; }
+.responses { border-top: 1px solid #ddd; margin-top: 1em; }
+#search { width: 100%; font-size: 1em; padding: .3em; }
+";
+
+const SEARCH_JS: &str = "function search(q) {
+  const out = document.getElementById('results');
+  const terms = q.toLowerCase().split(/\\s+/).filter(t => t);
+  out.innerHTML = '';
+  if (!terms.length) return;
+  for (const e of INDEX) {
+    const text = e.text.toLowerCase();
+    if (!terms.every(t => text.includes(t))) continue;
+    const li = document.createElement('li');
+    const a = document.createElement('a');
+    a.href = 'p/' + e.slug + '.html';
+    a.textContent = e.date + ' ' + e.name + ': ' + e.text.slice(0, 140);
+    li.appendChild(a);
+    out.appendChild(li);
+  }
+}
+";
+
+/// Escape text for html.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn page(title: &str, root: &str, body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <link rel=\"stylesheet\" href=\"{}style.css\">\n</head>\n<body>\n\
+         <nav><a href=\"{}index.html\">Index</a></nav>\n{}</body>\n</html>\n",
+        escape(title),
+        root,
+        root,
+        body
+    )
+}
+
+/// Url of the avatar picked by a user, or plurk's default one.
+fn avatar_url(u: &PlurkUser) -> String {
+    match (u.has_profile_image, u.avatar) {
+        (1, Some(avatar)) => format!("https://avatars.plurk.com/{}-medium{}.gif", u.id, avatar),
+        (1, None) => format!("https://avatars.plurk.com/{}-medium.gif", u.id),
+        _ => String::from("https://www.plurk.com/static/default_medium.jpg"),
+    }
+}
+
+/// Links of images in plurk content pointing at the exported copies. The
+/// `mx_` thumbnails are replaced by their full image.
+fn localize(content: &str, media: &HashMap<String, String>, root: &str) -> String {
+    let mut out = String::with_capacity(content.len());
+    let mut rest = content;
+    while let Some(start) = rest.find(IMAGE_PREFIX) {
+        out.push_str(&rest[..start]);
+        let url_len = rest[start..]
+            .find(['"', '\'', ' ', '<', '>'])
+            .unwrap_or(rest.len() - start);
+        let url = &rest[start..start + url_len];
+        let name = &url[IMAGE_PREFIX.len()..];
+        match media
+            .get(name)
+            .or_else(|| name.strip_prefix("mx_").and_then(|n| media.get(n)))
+        {
+            Some(file) => out.push_str(&format!("{}../{}", root, file)),
+            None => out.push_str(url),
+        }
+        rest = &rest[start + url_len..];
+    }
+    out.push_str(rest);
+    out
+}
+
+struct Site<'a> {
+    time: &'a TimeFormat,
+    users: HashMap<u64, PlurkUser>,
+    /// Exported file of each image, by its name on the image host.
+    media: HashMap<String, String>,
+}
+
+impl Site<'_> {
+    fn date(&self, t: &chrono::DateTime<chrono::FixedOffset>) -> String {
+        self.time.local(t).format(DATE_FORMAT).to_string()
+    }
+
+    fn name(&self, id: u64) -> String {
+        self.users
+            .get(&id)
+            .map(|u| u.display_name.clone())
+            .unwrap_or_else(|| id.to_string())
+    }
+
+    /// A plurk or response with the avatar and name of `user_id`.
+    fn entry(
+        &self,
+        user_id: u64,
+        posted: &chrono::DateTime<chrono::FixedOffset>,
+        qualifier: &str,
+        content: &str,
+        extra: &str,
+        root: &str,
+    ) -> String {
+        let qualifier = match qualifier {
+            "" | ":" => String::new(),
+            q => format!(" <span class=\"qualifier\">{}</span>", escape(q)),
+        };
+        let nick = self
+            .users
+            .get(&user_id)
+            .map(|u| u.nick_name.as_str())
+            .unwrap_or_default();
+        format!(
+            "<div class=\"post\">\n<img class=\"avatar\" src=\"{}{}/{}.gif\" alt=\"\">\n<div>\n\
+             <div class=\"meta\"><span class=\"name\" title=\"{}\">{}</span>{} · {}{}</div>\n\
+             <div class=\"content\">{}</div>\n</div>\n</div>\n",
+            root,
+            AVATAR_DIR,
+            user_id,
+            escape(nick),
+            escape(&self.name(user_id)),
+            qualifier,
+            self.date(posted),
+            extra,
+            localize(content, &self.media, root),
+        )
+    }
+
+    fn thread_page(&self, p: &PlurkData, responses: &[PlurkResponse]) -> String {
+        let mut body = self.entry(p.owner_id, &p.posted, &p.qualifier, &p.content, "", "../");
+        body.push_str("<div class=\"responses\">\n");
+        let nicks: HashMap<u64, String> = self
+            .users
+            .iter()
+            .map(|(id, u)| (*id, u.nick_name.clone()))
+            .collect();
+        for (i, depth) in thread_order(responses, &nicks, false) {
+            let r = &responses[i];
+            body.push_str(&format!(
+                "<div style=\"margin-left: {}em\">\n{}</div>\n",
+                depth * 2,
+                self.entry(r.user_id, &r.posted, &r.qualifier, &r.content, "", "../")
+            ));
+        }
+        body.push_str("</div>\n");
+        let title = format!("{}: {}", self.name(p.owner_id), p.content_raw);
+        page(&title.chars().take(80).collect::<String>(), "../", &body)
+    }
+}
+
+/// Render the export in `dir` as a static site in its `html` folder.
+pub async fn render(dir: &Path, time: &TimeFormat) -> Result<(), PlurkError> {
+    let parse = |e: serde_json::Error| PlurkError::ParseError(e.to_string());
+    let io = |e: std::io::Error| PlurkError::IOError(e.to_string());
+
+    let mut plurks: Vec<PlurkData> = read_jsonl(&dir.join(PLURKS_FILE))?
+        .into_iter()
+        .map(serde_json::from_value)
+        .collect::<Result<_, _>>()
+        .map_err(parse)?;
+    plurks.sort_by_key(|p| std::cmp::Reverse(p.posted));
+    let mut responses: HashMap<u64, Vec<PlurkResponse>> = HashMap::new();
+    for v in read_jsonl(&dir.join(RESPONSES_FILE))? {
+        let r: PlurkResponse = serde_json::from_value(v).map_err(parse)?;
+        responses.entry(r.plurk_id).or_default().push(r);
+    }
+    for list in responses.values_mut() {
+        list.sort_by_key(|r| r.id);
+    }
+    let users: HashMap<u64, PlurkUser> = read_jsonl(&dir.join(USERS_FILE))?
+        .into_iter()
+        .filter_map(|v| serde_json::from_value::<PlurkUser>(v).ok())
+        .map(|u| (u.id, u))
+        .collect();
+    let media: HashMap<String, String> = read_jsonl(&dir.join(MEDIA_FILE))?
+        .iter()
+        .filter_map(|v| {
+            let url = v["url"].as_str()?;
+            let file = v["file"].as_str()?;
+            Some((
+                url.strip_prefix(IMAGE_PREFIX)?.to_string(),
+                file.to_string(),
+            ))
+        })
+        .filter(|(_, file)| file.starts_with(MEDIA_DIR))
+        .collect();
+
+    let site_dir = dir.join(SITE_DIR);
+    fs::create_dir_all(site_dir.join("p")).map_err(io)?;
+    fs::create_dir_all(site_dir.join(AVATAR_DIR)).map_err(io)?;
+    fs::write(site_dir.join("style.css"), STYLE).map_err(io)?;
+
+    // Avatars are kept across runs, only new users are downloaded.
+    for u in users.values() {
+        let path = site_dir.join(AVATAR_DIR).join(format!("{}.gif", u.id));
+        if path.exists() {
+            continue;
+        }
+        match fetch(&avatar_url(u)).await {
+            Ok(data) => fs::write(&path, data).map_err(io)?,
+            Err(e) => eprintln!("Warning: avatar of {} ({})", u.nick_name, e),
+        }
+    }
+
+    let site = Site { time, users, media };
+    let mut months: BTreeMap<String, Vec<&PlurkData>> = BTreeMap::new();
+    let mut index = Vec::new();
+    for p in &plurks {
+        let slug = base36_encode(p.plurk_id);
+        let thread = responses
+            .get(&p.plurk_id)
+            .map(Vec::as_slice)
+            .unwrap_or_default();
+        fs::write(
+            site_dir.join("p").join(format!("{}.html", slug)),
+            site.thread_page(p, thread),
+        )
+        .map_err(io)?;
+        months
+            .entry(time.local(&p.posted).format("%Y-%m").to_string())
+            .or_default()
+            .push(p);
+
+        let mut text = p.content_raw.clone();
+        for r in thread {
+            text.push('\n');
+            text.push_str(&r.content_raw);
+        }
+        index.push(json!({
+            "slug": slug,
+            "date": site.date(&p.posted),
+            "name": site.name(p.owner_id),
+            "text": text,
+        }));
+    }
+
+    for (month, list) in &months {
+        let mut body = format!("<h1>{}</h1>\n", month);
+        for p in list {
+            let extra = format!(
+                " · <a href=\"p/{}.html\">{} responses</a>",
+                base36_encode(p.plurk_id),
+                p.response_count
+            );
+            body.push_str(&site.entry(p.owner_id, &p.posted, &p.qualifier, &p.content, &extra, ""));
+        }
+        fs::write(
+            site_dir.join(format!("{}.html", month)),
+            page(month, "", &body),
+        )
+        .map_err(io)?;
+    }
+
+    let mut body = String::from(
+        "<h1>Plurks</h1>\n<input id=\"search\" type=\"search\" placeholder=\"Search\" \
+         oninput=\"search(this.value)\">\n<ul id=\"results\"></ul>\n<ul>\n",
+    );
+    for (month, list) in months.iter().rev() {
+        body.push_str(&format!(
+            "<li><a href=\"{}.html\">{}</a> ({})</li>\n",
+            month,
+            month,
+            list.len()
+        ));
+    }
+    body.push_str(
+        "</ul>\n<script src=\"search-index.js\"></script>\n<script src=\"search.js\"></script>\n",
+    );
+    fs::write(site_dir.join("index.html"), page("Plurks", "", &body)).map_err(io)?;
+    fs::write(site_dir.join("search.js"), SEARCH_JS).map_err(io)?;
+    let index = Value::Array(index);
+    fs::write(
+        site_dir.join("search-index.js"),
+        format!("const INDEX = {};\n", index),
+    )
+    .map_err(io)?;
+
+    println!(
+        "Rendered {} plurks in {} months to {}",
+        plurks.len(),
+        months.len(),
+        site_dir.join("index.html").display()
+    );
+    Ok(())
+}
@@ -0,0 +1,159 @@
+// theme.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Colors of the printed plurks, configurable in the `[theme]` section.
+
+use clap::ValueEnum;
+use colored::{Color, ColoredString, Colorize};
+use serde::Deserialize;
+use std::env;
+use std::io::{self, IsTerminal};
+
+/// When to print colors and styles.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum ColorMode {
+    /// When stdout is a terminal and `NO_COLOR` isn't set
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorMode {
+    /// Turn colors on or off for everything printed with `colored`.
+    pub fn apply(self) {
+        let enabled = match self {
+            ColorMode::Always => true,
+            ColorMode::Never => false,
+            ColorMode::Auto => {
+                env::var_os("NO_COLOR").is_none_or(|v| v.is_empty()) && io::stdout().is_terminal()
+            }
+        };
+        colored::control::set_override(enabled);
+    }
+}
+
+/// A color spec like `bold bright blue on black` or `#ff8800`.
+#[derive(Clone, Debug, Default, PartialEq, Deserialize)]
+#[serde(try_from = "String")]
+pub struct Style {
+    fg: Option<Color>,
+    bg: Option<Color>,
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    dimmed: bool,
+}
+
+impl Style {
+    pub fn paint(&self, text: &str) -> ColoredString {
+        let mut s = text.normal();
+        if let Some(c) = self.fg {
+            s = s.color(c);
+        }
+        if let Some(c) = self.bg {
+            s = s.on_color(c);
+        }
+        if self.bold {
+            s = s.bold();
+        }
+        if self.italic {
+            s = s.italic();
+        }
+        if self.underline {
+            s = s.underline();
+        }
+        if self.dimmed {
+            s = s.dimmed();
+        }
+        s
+    }
+}
+
+impl TryFrom<String> for Style {
+    type Error = String;
+
+    fn try_from(spec: String) -> Result<Self, Self::Error> {
+        let mut style = Style::default();
+        let mut background = false;
+        let mut words = spec.split_whitespace();
+        while let Some(word) = words.next() {
+            let word = word.to_ascii_lowercase();
+            match word.as_str() {
+                "bold" => style.bold = true,
+                "italic" => style.italic = true,
+                "underline" => style.underline = true,
+                "dimmed" | "dim" => style.dimmed = true,
+                "normal" | "none" => {}
+                "on" => background = true,
+                _ => {
+                    let name = if word == "bright" {
+                        format!("bright {}", words.next().unwrap_or_default())
+                    } else {
+                        word
+                    };
+                    let color =
+                        parse_color(&name).ok_or_else(|| format!("unknown color `{}`", name))?;
+                    if background {
+                        style.bg = Some(color);
+                    } else {
+                        style.fg = Some(color);
+                    }
+                }
+            }
+        }
+        Ok(style)
+    }
+}
+
+/// A color name, or a hex color with or without `#` as used by
+/// `PlurkUser::name_color`.
+pub fn parse_color(name: &str) -> Option<Color> {
+    let hex = name.trim_start_matches('#');
+    if hex.len() == 6 && hex.chars().all(|c| c.is_ascii_hexdigit()) {
+        let v = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).ok();
+        return Some(Color::TrueColor {
+            r: v(0)?,
+            g: v(2)?,
+            b: v(4)?,
+        });
+    }
+    name.parse().ok()
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    pub timestamp: Style,
+    /// Display names of plurk owners.
+    pub name: Style,
+    /// Paint display names with the color users picked on plurk.
+    pub name_color: bool,
+    pub qualifier: Style,
+    /// Display names of responders.
+    pub response: Style,
+    pub separator: Style,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        let style = |spec: &str| Style::try_from(spec.to_string()).unwrap_or_default();
+        Theme {
+            timestamp: style("bright yellow"),
+            name: style("bold bright blue"),
+            name_color: false,
+            qualifier: style("black on bright white"),
+            response: style("bold bright magenta"),
+            separator: style("normal"),
+        }
+    }
+}
+
+impl Theme {
+    /// A plurk owner, in the color from their profile if enabled.
+    pub fn name(&self, name: &str, name_color: Option<&str>) -> ColoredString {
+        match name_color.filter(|_| self.name_color).and_then(parse_color) {
+            Some(c) => self.name.paint(name).color(c),
+            None => self.name.paint(name),
+        }
+    }
+}
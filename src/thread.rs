@@ -0,0 +1,172 @@
+// thread.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Arrange responses as a conversation tree from their `@nick` references.
+
+use crate::plurk::PlurkResponse;
+use regex::Regex;
+use std::collections::HashMap;
+use std::sync::LazyLock;
+
+// Anchored to the start or a space, so `me@example.com` isn't a mention.
+static MENTION_RE: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?:^|\s)@([A-Za-z0-9_]+)").unwrap());
+
+/// Responses are indented no deeper than this.
+const MAX_DEPTH: usize = 6;
+
+/// The nick a response is addressed to, e.g. `dephilia` in `@dephilia: yes`.
+fn addressed_to(content_raw: &str) -> Option<String> {
+    MENTION_RE
+        .captures(content_raw)
+        .map(|c| c[1].to_ascii_lowercase())
+}
+
+/// The response each one answers, if any. A response mentioning `@nick`
+/// answers the latest earlier response of that user; the others are top
+/// level.
+fn parents(responses: &[PlurkResponse], nicks: &HashMap<u64, String>) -> Vec<Option<usize>> {
+    // Latest response of each nick so far.
+    let mut latest: HashMap<String, usize> = HashMap::new();
+    let mut parents = Vec::with_capacity(responses.len());
+    for (i, r) in responses.iter().enumerate() {
+        parents.push(addressed_to(&r.content_raw).and_then(|nick| latest.get(&nick).copied()));
+        if let Some(nick) = nicks.get(&r.user_id) {
+            latest.insert(nick.to_ascii_lowercase(), i);
+        }
+    }
+    parents
+}
+
+/// Order of the responses as `(index, depth)`, each reply under the
+/// response it answers. With `flat` the chronological order is kept.
+pub fn thread_order(
+    responses: &[PlurkResponse],
+    nicks: &HashMap<u64, String>,
+    flat: bool,
+) -> Vec<(usize, usize)> {
+    if flat {
+        return (0..responses.len()).map(|i| (i, 0)).collect();
+    }
+
+    let mut children: Vec<Vec<usize>> = vec![Vec::new(); responses.len()];
+    let mut roots = Vec::new();
+    for (i, parent) in parents(responses, nicks).into_iter().enumerate() {
+        match parent {
+            Some(p) => children[p].push(i),
+            None => roots.push(i),
+        }
+    }
+
+    let mut order = Vec::with_capacity(responses.len());
+    let mut stack: Vec<(usize, usize)> = roots.iter().rev().map(|&i| (i, 0)).collect();
+    while let Some((i, depth)) = stack.pop() {
+        order.push((i, depth.min(MAX_DEPTH)));
+        stack.extend(children[i].iter().rev().map(|&c| (c, depth + 1)));
+    }
+    order
+}
+
+/// Which responses to show so the `wanted` ones keep their context: those
+/// and every response they reply to, up to the top level.
+pub fn with_ancestors<F>(
+    responses: &[PlurkResponse],
+    nicks: &HashMap<u64, String>,
+    wanted: F,
+) -> Vec<bool>
+where
+    F: Fn(&PlurkResponse) -> bool,
+{
+    let parents = parents(responses, nicks);
+    let mut keep: Vec<bool> = responses.iter().map(wanted).collect();
+    // Parents come before their replies, so walking back reaches the roots.
+    for i in (0..responses.len()).rev() {
+        if let (true, Some(p)) = (keep[i], parents[i]) {
+            keep[p] = true;
+        }
+    }
+    keep
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn response(id: u64, user_id: u64, content_raw: &str) -> PlurkResponse {
+        PlurkResponse {
+            id,
+            user_id,
+            content_raw: content_raw.to_string(),
+            ..Default::default()
+        }
+    }
+
+    fn nicks() -> HashMap<u64, String> {
+        [(1, "alice"), (2, "Bob"), (3, "carol")]
+            .into_iter()
+            .map(|(id, nick)| (id, nick.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn replies_nest_under_the_latest_response_of_the_nick() {
+        let responses = [
+            response(10, 1, "first"),
+            response(11, 2, "@alice: hi"),
+            response(12, 3, "unrelated"),
+            response(13, 1, "@bob thanks"),
+            response(14, 3, "@alice again"),
+        ];
+        assert_eq!(
+            thread_order(&responses, &nicks(), false),
+            [(0, 0), (1, 1), (3, 2), (4, 3), (2, 0)]
+        );
+    }
+
+    #[test]
+    fn flat_keeps_the_chronological_order() {
+        let responses = [response(10, 1, "first"), response(11, 2, "@alice: hi")];
+        assert_eq!(thread_order(&responses, &nicks(), true), [(0, 0), (1, 0)]);
+    }
+
+    #[test]
+    fn unknown_nicks_and_emails_are_top_level() {
+        let responses = [
+            response(10, 1, "first"),
+            response(11, 2, "@dave: who?"),
+            response(12, 3, "mail bob@alice.com"),
+        ];
+        assert_eq!(
+            thread_order(&responses, &nicks(), false),
+            [(0, 0), (1, 0), (2, 0)]
+        );
+    }
+
+    #[test]
+    fn ancestors_are_kept() {
+        let responses = [
+            response(10, 1, "first"),
+            response(11, 2, "@alice: hi"),
+            response(12, 3, "unrelated"),
+            response(13, 1, "@bob thanks"),
+            response(14, 3, "new"),
+        ];
+        assert_eq!(
+            with_ancestors(&responses, &nicks(), |r| r.id > 12),
+            [true, true, false, true, true]
+        );
+    }
+
+    #[test]
+    fn depth_is_capped() {
+        let responses: Vec<PlurkResponse> = (0..10)
+            .map(|i| {
+                let (user, to) = if i % 2 == 0 { (1, "bob") } else { (2, "alice") };
+                response(i, user, &format!("@{} more", to))
+            })
+            .collect();
+        let order = thread_order(&responses, &nicks(), false);
+        assert_eq!(order.len(), responses.len());
+        assert_eq!(order.iter().map(|&(_, d)| d).max(), Some(MAX_DEPTH));
+    }
+}
@@ -0,0 +1,129 @@
+// time.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Formatting of the `posted` timestamps.
+
+use crate::error::PlurkError;
+use chrono::{DateTime, Datelike, FixedOffset, Local, NaiveDate, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use clap::ValueEnum;
+use std::fs;
+use std::path::PathBuf;
+
+const DEFAULT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// How timestamps are printed.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum TimeMode {
+    /// "5m ago", "yesterday 21:03"
+    Relative,
+    /// Date and time in the configured format
+    Absolute,
+    /// RFC 3339
+    Iso,
+}
+
+/// `dateformat` in the data dir, e.g. `~/.local/share/plurk-cli`, holding the
+/// date order picked in the plurk settings, so it isn't fetched on every run.
+fn dateformat_path() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("plurk-cli")
+        .join("dateformat")
+}
+
+/// The date order saved by `init`, `login` or the first run needing it.
+pub fn cached_dateformat() -> Option<u8> {
+    fs::read_to_string(dateformat_path())
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+}
+
+pub fn cache_dateformat(dateformat: u8) -> Result<(), PlurkError> {
+    let path = dateformat_path();
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).map_err(|e| PlurkError::IOError(e.to_string()))?;
+    }
+    fs::write(&path, dateformat.to_string())
+        .map_err(|e| PlurkError::IOError(format!("{}: {}", path.display(), e)))
+}
+
+#[derive(Clone, Debug)]
+pub struct TimeFormat {
+    pub mode: TimeMode,
+    /// Zone to print in, the local one if not set.
+    pub tz: Option<Tz>,
+    /// strftime format of absolute timestamps.
+    pub format: Option<String>,
+}
+
+impl TimeFormat {
+    /// Format used when nothing is configured, following the `dateformat`
+    /// preference of the plurk user.
+    pub fn user_format(dateformat: u8) -> String {
+        let date = match dateformat {
+            0 => "%m/%d/%Y",
+            1 => "%d/%m/%Y",
+            2 => "%Y/%m/%d",
+            _ => "%Y-%m-%d",
+        };
+        format!("{} %H:%M:%S", date)
+    }
+
+    /// Move a timestamp into the configured zone.
+    pub fn local(&self, t: &DateTime<FixedOffset>) -> DateTime<FixedOffset> {
+        match self.tz {
+            Some(tz) => t.with_timezone(&tz).fixed_offset(),
+            None => t.with_timezone(&Local).fixed_offset(),
+        }
+    }
+
+    /// Unix time at which a day starts in the configured zone.
+    pub fn day_start(&self, day: NaiveDate) -> Option<i64> {
+        let start = day.and_time(NaiveTime::MIN);
+        match self.tz {
+            Some(tz) => tz
+                .from_local_datetime(&start)
+                .earliest()
+                .map(|t| t.timestamp()),
+            None => Local
+                .from_local_datetime(&start)
+                .earliest()
+                .map(|t| t.timestamp()),
+        }
+    }
+
+    pub fn format(&self, t: &DateTime<FixedOffset>) -> String {
+        let t = self.local(t);
+        match self.mode {
+            TimeMode::Absolute => t
+                .format(self.format.as_deref().unwrap_or(DEFAULT_FORMAT))
+                .to_string(),
+            TimeMode::Iso => t.to_rfc3339(),
+            TimeMode::Relative => self.relative(&t),
+        }
+    }
+
+    fn relative(&self, t: &DateTime<FixedOffset>) -> String {
+        let now = Utc::now().with_timezone(t.offset());
+        let secs = (now - *t).num_seconds();
+        let (day, today) = (t.date_naive(), now.date_naive());
+        if secs < 60 {
+            String::from("just now")
+        } else if secs < 60 * 60 {
+            format!("{}m ago", secs / 60)
+        } else if day == today {
+            format!("{}h ago", secs / 3600)
+        } else if today.pred_opt() == Some(day) {
+            t.format("yesterday %H:%M").to_string()
+        } else if secs < 6 * 24 * 60 * 60 {
+            t.format("%a %H:%M").to_string()
+        } else if t.year() == now.year() {
+            t.format("%b %d %H:%M").to_string()
+        } else {
+            t.format("%b %d %Y").to_string()
+        }
+    }
+}
@@ -0,0 +1,775 @@
+// tui.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+
+use crate::api::ObjGetResponses;
+use crate::comet::{CometContentUnit, CometFeed};
+use crate::error::PlurkError;
+use crate::plurk::{Plurk, PlurkData, PlurkResponse, PlurkUser};
+use crate::render::{parse_html, ContentMode, RenderOptions};
+use crate::thread::thread_order;
+use crate::utils::base36_encode;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind};
+use ratatui::layout::{Constraint, Direction, Layout, Position};
+use ratatui::style::{Color, Modifier, Style};
+use ratatui::text::{Line, Span, Text};
+use ratatui::widgets::{Block, Borders, List, ListItem, ListState, Paragraph, Wrap};
+use ratatui::{DefaultTerminal, Frame};
+use std::collections::HashMap;
+use std::future::Future;
+use std::io;
+use std::time::Duration;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+const HELP: &str = "j/k move  enter open  tab focus  c respond  n new  l like  R replurk  m mute  u read  g refresh  q quit";
+
+enum Feed {
+    Data(Box<CometContentUnit>),
+    Error(String),
+    /// Results of the api calls spawned by the ui.
+    Done(Box<Done>),
+    /// Terminal events, read on a blocking thread.
+    Input(io::Result<Event>),
+}
+
+enum Done {
+    Timeline(Vec<PlurkData>, HashMap<u64, PlurkUser>),
+    Profile(PlurkUser),
+    Thread(u64, ObjGetResponses),
+    Read(u64),
+    Liked(u64, bool),
+    Replurked(u64, bool),
+    Muted(u64),
+    Posted(PlurkData),
+    Responded(PlurkResponse),
+}
+
+#[derive(PartialEq)]
+enum Focus {
+    Timeline,
+    Thread,
+}
+
+enum Compose {
+    NewPlurk,
+    Respond(u64),
+}
+
+struct Thread {
+    plurk_id: u64,
+    responses: Vec<PlurkResponse>,
+    scroll: u16,
+}
+
+struct App {
+    plurk: Plurk,
+    limit: u64,
+    opts: RenderOptions,
+    tx: UnboundedSender<Feed>,
+    plurks: Vec<PlurkData>,
+    users: HashMap<u64, PlurkUser>,
+    list: ListState,
+    thread: Option<Thread>,
+    focus: Focus,
+    compose: Option<(Compose, String)>,
+    status: String,
+    quit: bool,
+}
+
+pub async fn run_tui(plurk: Plurk, limit: u64, opts: &RenderOptions) -> Result<(), PlurkError> {
+    let (tx, rx) = mpsc::unbounded_channel();
+    let mut app = App::new(plurk.clone(), limit, opts.clone(), tx.clone());
+    // Load before taking over the terminal, so errors are printed normally.
+    let (plurks, users) = timeline(&plurk, limit).await?;
+    app.set_timeline(plurks, users);
+
+    let feed = tokio::spawn(comet_feed(plurk, tx.clone()));
+    tokio::task::spawn_blocking(move || input_feed(tx));
+
+    let mut terminal = ratatui::init();
+    let res = app.run(&mut terminal, rx).await;
+    ratatui::restore();
+    feed.abort();
+    res
+}
+
+/// Plurks of the last day and their owners.
+async fn timeline(
+    plurk: &Plurk,
+    limit: u64,
+) -> Result<(Vec<PlurkData>, HashMap<u64, PlurkUser>), PlurkError> {
+    let time = chrono::offset::Utc::now() - chrono::Duration::days(1);
+    let time = time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true);
+    let body = plurk.get_plurks(&time, limit).await?;
+    Ok((
+        body.plurks.unwrap_or_default(),
+        body.plurk_users.unwrap_or_default(),
+    ))
+}
+
+/// Forward comet events to the ui until the receiver is dropped.
+async fn comet_feed(plurk: Plurk, tx: UnboundedSender<Feed>) {
+    let mut feed = CometFeed::new(plurk);
+    loop {
+        match feed.next().await {
+            Ok(datas) => {
+                for data in datas {
+                    if tx.send(Feed::Data(Box::new(data))).is_err() {
+                        return;
+                    }
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(Feed::Error(format!("comet unavailable: {}", e)));
+                return;
+            }
+        }
+    }
+}
+
+/// Forward terminal events to the ui until the receiver is dropped. Reading
+/// blocks, so this runs on its own thread and checks the receiver now and
+/// then.
+fn input_feed(tx: UnboundedSender<Feed>) {
+    while !tx.is_closed() {
+        match event::poll(Duration::from_millis(200)) {
+            Ok(false) => {}
+            Ok(true) => {
+                if tx.send(Feed::Input(event::read())).is_err() {
+                    return;
+                }
+            }
+            Err(e) => {
+                let _ = tx.send(Feed::Input(Err(e)));
+                return;
+            }
+        }
+    }
+}
+
+impl App {
+    fn new(plurk: Plurk, limit: u64, opts: RenderOptions, tx: UnboundedSender<Feed>) -> Self {
+        Self {
+            plurk,
+            limit,
+            opts,
+            tx,
+            plurks: Vec::new(),
+            users: HashMap::new(),
+            list: ListState::default(),
+            thread: None,
+            focus: Focus::Timeline,
+            compose: None,
+            status: String::from(HELP),
+            quit: false,
+        }
+    }
+
+    async fn run(
+        &mut self,
+        terminal: &mut DefaultTerminal,
+        mut rx: UnboundedReceiver<Feed>,
+    ) -> Result<(), PlurkError> {
+        while !self.quit {
+            terminal
+                .draw(|f| self.draw(f))
+                .map_err(|e| PlurkError::IOError(e.to_string()))?;
+
+            // Wait for something to happen, then take whatever else queued
+            // up before drawing again.
+            let Some(feed) = rx.recv().await else {
+                break;
+            };
+            self.on_event(feed)?;
+            while let Ok(feed) = rx.try_recv() {
+                self.on_event(feed)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn on_event(&mut self, feed: Feed) -> Result<(), PlurkError> {
+        match feed {
+            Feed::Input(ev) => {
+                let ev = ev.map_err(|e| PlurkError::IOError(e.to_string()))?;
+                if let Event::Key(key) = ev {
+                    if key.kind == KeyEventKind::Press {
+                        self.on_key(key);
+                    }
+                }
+            }
+            feed => self.on_feed(feed),
+        }
+        Ok(())
+    }
+
+    /// Run an api call in the background, its result comes back as a feed so
+    /// the ui keeps drawing meanwhile.
+    fn spawn<F>(&self, call: F)
+    where
+        F: Future<Output = Result<Done, PlurkError>> + Send + 'static,
+    {
+        let tx = self.tx.clone();
+        tokio::spawn(async move {
+            let _ = tx.send(match call.await {
+                Ok(done) => Feed::Done(Box::new(done)),
+                Err(e) => Feed::Error(e.to_string()),
+            });
+        });
+    }
+
+    fn set_timeline(&mut self, plurks: Vec<PlurkData>, users: HashMap<u64, PlurkUser>) {
+        self.plurks = plurks;
+        self.plurks.retain(|p| !self.opts.hides(p));
+        self.users.extend(users);
+        if self.plurks.is_empty() {
+            self.list.select(None);
+        } else if self.list.selected().is_none_or(|i| i >= self.plurks.len()) {
+            self.list.select(Some(0));
+        }
+    }
+
+    fn refresh(&mut self) {
+        let (plurk, limit) = (self.plurk.clone(), self.limit);
+        self.spawn(async move {
+            let (plurks, users) = timeline(&plurk, limit).await?;
+            Ok(Done::Timeline(plurks, users))
+        });
+    }
+
+    fn selected(&self) -> Option<&PlurkData> {
+        self.list.selected().and_then(|i| self.plurks.get(i))
+    }
+
+    fn find_mut(&mut self, plurk_id: u64) -> Option<&mut PlurkData> {
+        self.plurks.iter_mut().find(|p| p.plurk_id == plurk_id)
+    }
+
+    fn display_name(&self, user_id: u64) -> String {
+        match self.users.get(&user_id) {
+            Some(u) => u.display_name.clone(),
+            None => user_id.to_string(),
+        }
+    }
+
+    fn open_thread(&mut self) {
+        let Some(p) = self.selected() else {
+            return;
+        };
+        let (plurk_id, unread) = (p.plurk_id, p.is_unread != 0);
+        let plurk = self.plurk.clone();
+        self.spawn(async move {
+            let body = plurk.get_responses(plurk_id, 0).await?;
+            Ok(Done::Thread(plurk_id, body))
+        });
+        if unread {
+            self.mark_read();
+        }
+    }
+
+    fn mark_read(&mut self) {
+        let Some(p) = self.selected() else {
+            return;
+        };
+        let (plurk_id, plurk) = (p.plurk_id, self.plurk.clone());
+        self.spawn(async move {
+            plurk.timeline_ids("markAsRead", &[plurk_id]).await?;
+            Ok(Done::Read(plurk_id))
+        });
+    }
+
+    fn toggle_like(&mut self) {
+        let Some(p) = self.selected() else {
+            return;
+        };
+        let (plurk_id, favorite, plurk) = (p.plurk_id, p.favorite, self.plurk.clone());
+        self.spawn(async move {
+            let api = if favorite {
+                "unfavoritePlurks"
+            } else {
+                "favoritePlurks"
+            };
+            plurk.timeline_ids(api, &[plurk_id]).await?;
+            Ok(Done::Liked(plurk_id, !favorite))
+        });
+    }
+
+    fn toggle_replurk(&mut self) {
+        let Some(p) = self.selected() else {
+            return;
+        };
+        let (plurk_id, replurked, plurk) = (p.plurk_id, p.replurked, self.plurk.clone());
+        self.spawn(async move {
+            let api = if replurked { "unreplurk" } else { "replurk" };
+            plurk.timeline_ids(api, &[plurk_id]).await?;
+            Ok(Done::Replurked(plurk_id, !replurked))
+        });
+    }
+
+    fn mute(&mut self) {
+        let Some(p) = self.selected() else {
+            return;
+        };
+        let (plurk_id, plurk) = (p.plurk_id, self.plurk.clone());
+        self.spawn(async move {
+            plurk.timeline_ids("mutePlurks", &[plurk_id]).await?;
+            Ok(Done::Muted(plurk_id))
+        });
+    }
+
+    fn send(&mut self, target: Compose, content: String) {
+        if content.trim().is_empty() {
+            return;
+        }
+        let plurk = self.plurk.clone();
+        match target {
+            Compose::NewPlurk => {
+                self.spawn(
+                    async move { Ok(Done::Posted(plurk.plurk_add(&content, ":", None).await?)) },
+                )
+            }
+            Compose::Respond(plurk_id) => self.spawn(async move {
+                let r = plurk.response_add(plurk_id, &content, ":").await?;
+                Ok(Done::Responded(r))
+            }),
+        }
+        self.status = String::from("Sending…");
+    }
+
+    fn on_key(&mut self, key: KeyEvent) {
+        if let Some((target, mut input)) = self.compose.take() {
+            match key.code {
+                KeyCode::Esc => {}
+                KeyCode::Enter => self.send(target, input),
+                KeyCode::Backspace => {
+                    input.pop();
+                    self.compose = Some((target, input));
+                }
+                KeyCode::Char(c) => {
+                    input.push(c);
+                    self.compose = Some((target, input));
+                }
+                _ => self.compose = Some((target, input)),
+            }
+            return;
+        }
+
+        match key.code {
+            KeyCode::Char('q') => self.quit = true,
+            KeyCode::Esc if self.focus == Focus::Thread => self.focus = Focus::Timeline,
+            KeyCode::Esc => self.quit = true,
+            KeyCode::Tab => {
+                self.focus = match self.focus {
+                    Focus::Timeline if self.thread.is_some() => Focus::Thread,
+                    _ => Focus::Timeline,
+                }
+            }
+            KeyCode::Char('j') | KeyCode::Down => match self.focus {
+                Focus::Timeline => self.list.select_next(),
+                Focus::Thread => {
+                    if let Some(t) = self.thread.as_mut() {
+                        t.scroll = t.scroll.saturating_add(1);
+                    }
+                }
+            },
+            KeyCode::Char('k') | KeyCode::Up => match self.focus {
+                Focus::Timeline => self.list.select_previous(),
+                Focus::Thread => {
+                    if let Some(t) = self.thread.as_mut() {
+                        t.scroll = t.scroll.saturating_sub(1);
+                    }
+                }
+            },
+            KeyCode::Enter => self.open_thread(),
+            KeyCode::Char('c') => {
+                let target = match (&self.focus, &self.thread, self.selected()) {
+                    (Focus::Thread, Some(t), _) => Some(t.plurk_id),
+                    (_, _, Some(p)) => Some(p.plurk_id),
+                    _ => None,
+                };
+                if let Some(plurk_id) = target {
+                    self.compose = Some((Compose::Respond(plurk_id), String::new()));
+                }
+            }
+            KeyCode::Char('n') => self.compose = Some((Compose::NewPlurk, String::new())),
+            KeyCode::Char('l') => self.toggle_like(),
+            KeyCode::Char('R') => self.toggle_replurk(),
+            KeyCode::Char('m') => self.mute(),
+            KeyCode::Char('u') => self.mark_read(),
+            KeyCode::Char('g') => self.refresh(),
+            _ => {}
+        }
+    }
+
+    fn on_feed(&mut self, feed: Feed) {
+        let data = match feed {
+            Feed::Data(data) => *data,
+            Feed::Error(e) => {
+                self.status = e;
+                return;
+            }
+            Feed::Done(done) => return self.on_done(*done),
+            Feed::Input(_) => return,
+        };
+        if data.hidden(&self.opts) {
+            return;
+        }
+        match data {
+            CometContentUnit::Plurk(p) => {
+                if self.plurks.iter().any(|x| x.plurk_id == p.plurk_id) {
+                    return;
+                }
+                if !self.users.contains_key(&p.owner_id) {
+                    let (user_id, plurk) = (p.owner_id, self.plurk.clone());
+                    self.spawn(async move {
+                        Ok(Done::Profile(plurk.get_public_profile(user_id).await?))
+                    });
+                }
+                self.status = format!("New plurk from {}", self.display_name(p.owner_id));
+                self.insert_top(p);
+            }
+            CometContentUnit::Response {
+                plurk_id,
+                plurk_data,
+                response,
+                user,
+                ..
+            } => {
+                for (id, u) in user {
+                    if let Ok(id) = id.parse() {
+                        self.users.insert(id, u);
+                    }
+                }
+                self.status = format!("New response from {}", self.display_name(response.user_id));
+                if let Some(t) = self.thread.as_mut().filter(|t| t.plurk_id == plurk_id) {
+                    if !t.responses.iter().any(|x| x.id == response.id) {
+                        t.responses.push(response);
+                    }
+                }
+                match self.find_mut(plurk_id) {
+                    Some(p) => *p = *plurk_data,
+                    None => self.insert_top(*plurk_data),
+                }
+            }
+            CometContentUnit::Notification { counts } => {
+                self.status = counts.summary();
+            }
+        }
+    }
+
+    /// Apply the result of a spawned api call.
+    fn on_done(&mut self, done: Done) {
+        match done {
+            Done::Timeline(plurks, users) => {
+                self.set_timeline(plurks, users);
+                self.status = String::from("Refreshed");
+            }
+            Done::Profile(u) => {
+                self.users.insert(u.id, u);
+            }
+            Done::Thread(plurk_id, mut body) => {
+                body.responses
+                    .retain(|r| !self.opts.blocked.contains(&r.user_id));
+                self.users.extend(body.friends);
+                self.thread = Some(Thread {
+                    plurk_id,
+                    responses: body.responses,
+                    scroll: 0,
+                });
+                self.focus = Focus::Thread;
+            }
+            Done::Read(plurk_id) => {
+                if let Some(p) = self.find_mut(plurk_id) {
+                    p.is_unread = 0;
+                }
+                self.status = String::from("Marked as read");
+            }
+            Done::Liked(plurk_id, favorite) => {
+                if let Some(p) = self.find_mut(plurk_id) {
+                    p.favorite = favorite;
+                    if favorite {
+                        p.favorite_count += 1;
+                    } else {
+                        p.favorite_count = p.favorite_count.saturating_sub(1);
+                    }
+                }
+                self.status = String::from(if favorite { "Liked" } else { "Unliked" });
+            }
+            Done::Replurked(plurk_id, replurked) => {
+                if let Some(p) = self.find_mut(plurk_id) {
+                    p.replurked = replurked;
+                }
+                self.status = String::from(if replurked {
+                    "Replurked"
+                } else {
+                    "Replurk removed"
+                });
+            }
+            Done::Muted(plurk_id) => {
+                let Some(i) = self.plurks.iter().position(|p| p.plurk_id == plurk_id) else {
+                    return;
+                };
+                self.plurks.remove(i);
+                if self.thread.as_ref().is_some_and(|t| t.plurk_id == plurk_id) {
+                    self.thread = None;
+                    self.focus = Focus::Timeline;
+                }
+                match self.list.selected() {
+                    _ if self.plurks.is_empty() => self.list.select(None),
+                    Some(s) if s > i || s >= self.plurks.len() => self.list.select(Some(s - 1)),
+                    _ => {}
+                }
+                self.status = String::from("Muted");
+            }
+            Done::Posted(p) => {
+                if !self.plurks.iter().any(|x| x.plurk_id == p.plurk_id) {
+                    self.insert_top(p);
+                }
+                self.status = String::from("Plurk posted");
+            }
+            Done::Responded(r) => {
+                if let Some(t) = self.thread.as_mut().filter(|t| t.plurk_id == r.plurk_id) {
+                    if !t.responses.iter().any(|x| x.id == r.id) {
+                        t.responses.push(r);
+                    }
+                }
+                self.status = String::from("Response posted");
+            }
+        }
+    }
+
+    /// Insert a plurk at the top, keeping the same plurk selected.
+    fn insert_top(&mut self, p: PlurkData) {
+        self.plurks.insert(0, p);
+        match self.list.selected() {
+            Some(i) => self.list.select(Some(i + 1)),
+            None => self.list.select(Some(0)),
+        }
+    }
+
+    fn draw(&mut self, frame: &mut Frame) {
+        let compose_height = if self.compose.is_some() { 3 } else { 0 };
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Min(3),
+                Constraint::Length(compose_height),
+                Constraint::Length(1),
+            ])
+            .split(frame.area());
+        let panes = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints([Constraint::Percentage(45), Constraint::Percentage(55)])
+            .split(rows[0]);
+
+        let border = |focused: bool| {
+            if focused {
+                Style::default().fg(Color::Cyan)
+            } else {
+                Style::default()
+            }
+        };
+
+        let items: Vec<ListItem> = self
+            .plurks
+            .iter()
+            .map(|p| {
+                let mut style = Style::default();
+                if p.is_unread != 0 {
+                    style = style.add_modifier(Modifier::BOLD);
+                }
+                let marker = if p.is_unread != 0 { "● " } else { "  " };
+                let content = self
+                    .content_lines(&p.content_raw, &p.content)
+                    .into_iter()
+                    .next()
+                    .unwrap_or_default();
+                let mut spans = vec![
+                    Span::styled(marker, Style::default().fg(Color::LightRed)),
+                    Span::styled(
+                        self.opts.time.local(&p.posted).format("%H:%M ").to_string(),
+                        Style::default().fg(Color::Yellow),
+                    ),
+                    Span::styled(
+                        self.display_name(p.owner_id),
+                        Style::default().fg(Color::LightBlue),
+                    ),
+                    Span::raw(" "),
+                    Span::styled(
+                        p.qualifier.clone(),
+                        Style::default().fg(Color::Black).bg(Color::White),
+                    ),
+                    Span::raw(" "),
+                ];
+                spans.extend(content);
+                ListItem::new(Line::from(spans)).style(style)
+            })
+            .collect();
+        let list = List::new(items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(" Timeline ")
+                    .border_style(border(self.focus == Focus::Timeline)),
+            )
+            .highlight_style(Style::default().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, panes[0], &mut self.list);
+
+        let (text, scroll) = match &self.thread {
+            Some(t) => (self.thread_text(t), t.scroll),
+            None => (Text::from("Press enter to open a plurk"), 0),
+        };
+        let thread = Paragraph::new(text)
+            .wrap(Wrap { trim: false })
+            .scroll((scroll, 0))
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .title(" Thread ")
+                    .border_style(border(self.focus == Focus::Thread)),
+            );
+        frame.render_widget(thread, panes[1]);
+
+        if let Some((target, input)) = &self.compose {
+            let title = match target {
+                Compose::NewPlurk => String::from(" New plurk "),
+                Compose::Respond(id) => format!(" Respond to {} ", base36_encode(*id)),
+            };
+            let line = Line::from(input.as_str());
+            let width = line.width() as u16;
+            frame.render_widget(
+                Paragraph::new(line).block(Block::default().borders(Borders::ALL).title(title)),
+                rows[1],
+            );
+            frame.set_cursor_position(Position::new(rows[1].x + 1 + width, rows[1].y + 1));
+        }
+
+        frame.render_widget(
+            Paragraph::new(self.status.as_str()).style(Style::default().fg(Color::DarkGray)),
+            rows[2],
+        );
+    }
+
+    fn thread_text(&self, t: &Thread) -> Text<'static> {
+        let mut lines = Vec::new();
+        if let Some(p) = self.plurks.iter().find(|p| p.plurk_id == t.plurk_id) {
+            lines.push(Line::from(vec![
+                Span::styled(
+                    self.display_name(p.owner_id),
+                    Style::default()
+                        .fg(Color::LightBlue)
+                        .add_modifier(Modifier::BOLD),
+                ),
+                Span::raw(" "),
+                Span::styled(
+                    p.qualifier.clone(),
+                    Style::default().fg(Color::Black).bg(Color::White),
+                ),
+            ]));
+            lines.push(Line::styled(
+                format!(
+                    "{}  https://www.plurk.com/p/{}  ♥{}{}",
+                    self.opts.timestamp(&p.posted),
+                    base36_encode(p.plurk_id),
+                    p.favorite_count,
+                    if p.replurked { "  replurked" } else { "" }
+                ),
+                Style::default().fg(Color::Yellow),
+            ));
+            for l in self.content_lines(&p.content_raw, &p.content) {
+                lines.push(Line::from(l));
+            }
+            lines.push(Line::styled(
+                "─".repeat(20),
+                Style::default().fg(Color::DarkGray),
+            ));
+        }
+        let nicks: HashMap<u64, String> = self
+            .users
+            .iter()
+            .map(|(id, u)| (*id, u.nick_name.clone()))
+            .collect();
+        for (i, depth) in thread_order(&t.responses, &nicks, false) {
+            let r = &t.responses[i];
+            let indent = "  ".repeat(depth);
+            let mut content = self.content_lines(&r.content_raw, &r.content).into_iter();
+            let mut spans = vec![
+                Span::raw(indent.clone()),
+                Span::styled(
+                    self.opts
+                        .time
+                        .local(&r.posted)
+                        .format("%m-%d %H:%M ")
+                        .to_string(),
+                    Style::default().fg(Color::Yellow),
+                ),
+                Span::styled(
+                    self.display_name(r.user_id),
+                    Style::default().fg(Color::LightMagenta),
+                ),
+                Span::raw(" "),
+                Span::styled(
+                    r.qualifier.clone(),
+                    Style::default().fg(Color::Black).bg(Color::White),
+                ),
+                Span::raw(" "),
+            ];
+            spans.extend(content.next().unwrap_or_default());
+            lines.push(Line::from(spans));
+            lines.extend(content.map(|l| {
+                let mut line = vec![Span::raw(indent.clone())];
+                line.extend(l);
+                Line::from(line)
+            }));
+        }
+        Text::from(lines)
+    }
+
+    /// Content split in lines of styled spans, following `--content`.
+    fn content_lines(&self, raw: &str, html: &str) -> Vec<Vec<Span<'static>>> {
+        let plain = |text: &str| {
+            text.lines()
+                .map(|l| vec![Span::raw(l.to_string())])
+                .collect()
+        };
+        let segments = match self.opts.content {
+            ContentMode::Raw => return plain(raw),
+            ContentMode::Html => return plain(html),
+            ContentMode::Rendered => parse_html(html),
+        };
+
+        let mut lines = vec![Vec::new()];
+        for seg in segments {
+            let mut style = Style::default();
+            if seg.style.bold {
+                style = style.add_modifier(Modifier::BOLD);
+            }
+            if seg.style.italic {
+                style = style.add_modifier(Modifier::ITALIC);
+            }
+            if seg.style.underline || (seg.link.is_some() && !seg.style.mention) {
+                style = style.add_modifier(Modifier::UNDERLINED);
+            }
+            if seg.style.strike {
+                style = style.add_modifier(Modifier::CROSSED_OUT);
+            }
+            if seg.style.code {
+                style = style.add_modifier(Modifier::REVERSED);
+            }
+            if seg.style.mention {
+                style = style.fg(Color::LightBlue).add_modifier(Modifier::BOLD);
+            }
+            for (i, part) in seg.text.split('\n').enumerate() {
+                if i > 0 {
+                    lines.push(Vec::new());
+                }
+                if !part.is_empty() {
+                    if let Some(line) = lines.last_mut() {
+                        line.push(Span::styled(part.to_string(), style));
+                    }
+                }
+            }
+        }
+        lines
+    }
+}
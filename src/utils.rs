@@ -3,8 +3,11 @@
 // Distributed under terms of the MIT license.
 
 use chrono::{self, DateTime, FixedOffset};
-use serde::{Deserialize, Deserializer};
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
 use std::cmp;
+use std::fs::{self, File, OpenOptions};
+use std::io;
+use std::path::Path;
 
 pub fn base36_encode(value: u64) -> String {
     let base36 = "0123456789abcdefghijklmnopqrstuvwxyz".as_bytes();
@@ -15,7 +18,7 @@ pub fn base36_encode(value: u64) -> String {
         let i: usize = v as usize % 36;
         let b: char = base36[i] as char;
         result.push(b);
-        v = v / 36;
+        v /= 36;
         if v == 0 {
             break;
         }
@@ -24,9 +27,47 @@ pub fn base36_encode(value: u64) -> String {
     result.chars().rev().collect::<String>()
 }
 
+pub fn base36_decode(value: &str) -> Option<u64> {
+    u64::from_str_radix(value, 36).ok()
+}
+
+/// Accept the base36 slug of a plurk, its `https://www.plurk.com/p/<slug>`
+/// url, or its decimal id marked as `id:<id>`.
+pub fn parse_plurk_id(value: &str) -> Option<u64> {
+    let value = value.trim();
+    if let Some(id) = value.strip_prefix("id:") {
+        // `parse` alone would also take a leading `+`.
+        if !id.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        return id.parse().ok();
+    }
+    let slug = match value.rsplit_once("/p/") {
+        Some((host, path)) => {
+            let host = host
+                .trim_start_matches("https://")
+                .trim_start_matches("http://");
+            let host = host.trim_end_matches("/m");
+            if !matches!(host, "plurk.com" | "www.plurk.com") {
+                return None;
+            }
+            path.split(['?', '#'])
+                .next()
+                .unwrap_or_default()
+                .trim_end_matches('/')
+        }
+        None => value,
+    };
+    let is_slug = |c: char| c.is_ascii_digit() || c.is_ascii_lowercase();
+    if slug.is_empty() || !slug.chars().all(is_slug) {
+        return None;
+    }
+    base36_decode(slug)
+}
+
 #[allow(dead_code)]
 pub fn limit_str(text: &str, limit: usize) -> String {
-    let text_size = text.chars().count() as usize;
+    let text_size = text.chars().count();
     let text = match text.find('\n') {
         Some(size) => match text.get(0..size) {
             Some(r) => r,
@@ -35,10 +76,10 @@ pub fn limit_str(text: &str, limit: usize) -> String {
         None => text,
     };
 
-    let str_size = text.chars().count() as usize;
+    let str_size = text.chars().count();
     let limit = cmp::min(limit, str_size);
 
-    let mut ret: String = text.chars().take(limit).skip(0).collect();
+    let mut ret: String = text.chars().take(limit).collect();
 
     if ret.chars().count() < text_size {
         ret.push_str(" ...<read more> ");
@@ -50,10 +91,102 @@ pub fn from_rfc2822<'de, D>(deserializer: D) -> Result<DateTime<FixedOffset>, D:
 where
     D: Deserializer<'de>,
 {
-    let s: &str = Deserialize::deserialize(deserializer)?;
+    let s: String = Deserialize::deserialize(deserializer)?;
 
-    Ok(DateTime::parse_from_rfc2822(&s).unwrap())
+    DateTime::parse_from_rfc2822(&s).map_err(D::Error::custom)
 }
 
-#[derive(Debug, Deserialize)]
-pub struct WrappedDT(#[serde(deserialize_with = "from_rfc2822")] DateTime<FixedOffset>);
+/// Serialize the way the Plurk API sends timestamps, so `from_rfc2822` reads
+/// them back.
+pub fn to_rfc2822<S>(t: &DateTime<FixedOffset>, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    serializer.serialize_str(&t.to_rfc2822())
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone)]
+pub struct WrappedDT(
+    #[serde(deserialize_with = "from_rfc2822", serialize_with = "to_rfc2822")]
+    pub  DateTime<FixedOffset>,
+);
+
+/// Create (or truncate) a file readable only by the owner.
+pub fn create_private_file<P: AsRef<Path>>(path: P) -> io::Result<File> {
+    let mut options = OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let file = options.open(&path)?;
+    // `mode` only applies on creation, tighten files that already existed.
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600))?;
+    }
+    Ok(file)
+}
+
+/// Warn when a file holding secrets is readable by group or others.
+pub fn warn_if_exposed<P: AsRef<Path>>(path: P) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        if let Ok(meta) = fs::metadata(&path) {
+            let mode = meta.permissions().mode();
+            if mode & 0o077 != 0 {
+                eprintln!(
+                    "Warning: {} is accessible by other users (mode {:o}), run `chmod 600` on it",
+                    path.as_ref().display(),
+                    mode & 0o777
+                );
+            }
+        }
+    }
+    #[cfg(not(unix))]
+    let _ = path;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plurk_id_from_slug_and_url() {
+        let id = base36_decode("3abcd1").unwrap();
+        assert_eq!(parse_plurk_id("3abcd1"), Some(id));
+        assert_eq!(parse_plurk_id("https://www.plurk.com/p/3abcd1"), Some(id));
+        assert_eq!(parse_plurk_id("http://plurk.com/p/3abcd1/"), Some(id));
+        assert_eq!(parse_plurk_id("https://www.plurk.com/m/p/3abcd1"), Some(id));
+        assert_eq!(
+            parse_plurk_id("https://www.plurk.com/p/3abcd1?r=1#top"),
+            Some(id)
+        );
+        assert_eq!(base36_encode(id), "3abcd1");
+    }
+
+    #[test]
+    fn plurk_id_decimal_needs_marker() {
+        assert_eq!(parse_plurk_id("id:1234"), Some(1234));
+        // Without the marker digits are a slug.
+        assert_eq!(parse_plurk_id("1234"), base36_decode("1234"));
+        assert_eq!(parse_plurk_id("id:+1234"), None);
+        assert_eq!(parse_plurk_id("id:12ab"), None);
+        assert_eq!(parse_plurk_id("id:"), None);
+    }
+
+    #[test]
+    fn plurk_id_rejects_other_input() {
+        assert_eq!(parse_plurk_id(""), None);
+        assert_eq!(parse_plurk_id("+3abc"), None);
+        assert_eq!(parse_plurk_id("3ABC"), None);
+        assert_eq!(parse_plurk_id("abc-def"), None);
+        assert_eq!(parse_plurk_id("https://example.com/p/3abcd1"), None);
+        assert_eq!(parse_plurk_id("https://www.plurk.com/p/"), None);
+        // Too large for a u64.
+        assert_eq!(parse_plurk_id("zzzzzzzzzzzzzzzz"), None);
+    }
+}
@@ -0,0 +1,169 @@
+// watch.rs
+// Copyright (C) 2022 dephilia <me@dephilia.moe>
+// Distributed under terms of the MIT license.
+// Follow the new responses of one plurk.
+
+use crate::archive::{record, Archive};
+use crate::comet::{CometContentUnit, CometFeed};
+use crate::error::PlurkError;
+use crate::images::preview;
+use crate::plurk::{Plurk, PlurkResponse};
+use crate::render::RenderOptions;
+use crate::utils::{base36_encode, parse_plurk_id};
+use std::time::Duration;
+use tokio::signal;
+use tokio::time::MissedTickBehavior;
+
+struct Watch<'a> {
+    plurk: Plurk,
+    archive: Option<&'a Archive>,
+    plurk_id: u64,
+    /// Responses fetched so far, the `from_response` of the next poll.
+    seen: u64,
+    last_id: u64,
+}
+
+impl Watch<'_> {
+    async fn print(&mut self, r: &PlurkResponse, name: &str, opts: &RenderOptions) {
+        if r.id <= self.last_id {
+            return;
+        }
+        self.last_id = r.id;
+        if opts.blocked.contains(&r.user_id) {
+            return;
+        }
+        println!("{}", opts.response(r, name, 0));
+        preview(opts, &r.content).await;
+    }
+
+    /// New responses from the comet channel, errors when it's unavailable.
+    /// Comet only carries the plurks on your timeline, so the responses are
+    /// also fetched every `interval` in case the plurk isn't there.
+    async fn comet(&mut self, interval: Duration, opts: &RenderOptions) -> Result<(), PlurkError> {
+        let mut feed = CometFeed::new(self.plurk.clone());
+        let mut tick = tokio::time::interval(interval);
+        tick.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        // The first tick is immediate, the responses were just fetched.
+        tick.tick().await;
+        loop {
+            let datas = tokio::select! {
+                datas = feed.next() => datas?,
+                _ = tick.tick() => {
+                    if let Err(e) = self.fetch(opts).await {
+                        eprintln!("{}", e);
+                    }
+                    continue;
+                }
+            };
+            for data in datas {
+                if let CometContentUnit::Response {
+                    plurk_id,
+                    response,
+                    response_count,
+                    user,
+                    ..
+                } = data
+                {
+                    if plurk_id != self.plurk_id {
+                        continue;
+                    }
+                    record(self.archive, [], [&response], user.values());
+                    let name = user
+                        .get(&response.user_id.to_string())
+                        .map(|u| u.display_name.clone())
+                        .unwrap_or_else(|| response.user_id.to_string());
+                    self.seen = self.seen.max(response_count);
+                    self.print(&response, &name, opts).await;
+                }
+            }
+        }
+    }
+
+    /// New responses from `/APP/Responses/get`, after the ones seen.
+    async fn fetch(&mut self, opts: &RenderOptions) -> Result<(), PlurkError> {
+        let body = self
+            .plurk
+            .get_all_responses(self.plurk_id, self.seen)
+            .await?;
+        self.seen += body.responses.len() as u64;
+        record(self.archive, [], &body.responses, body.friends.values());
+        for r in &body.responses {
+            let name = body
+                .friends
+                .get(&r.user_id)
+                .map(|u| u.display_name.clone())
+                .unwrap_or_else(|| r.user_id.to_string());
+            self.print(r, &name, opts).await;
+        }
+        Ok(())
+    }
+
+    /// New responses from polling alone.
+    async fn poll(&mut self, interval: Duration, opts: &RenderOptions) -> Result<(), PlurkError> {
+        loop {
+            tokio::time::sleep(interval).await;
+            if let Err(e) = self.fetch(opts).await {
+                eprintln!("{}", e);
+            }
+        }
+    }
+}
+
+/// Print a plurk, then its new responses until ctrl+c. Uses comet along with
+/// polling every `interval`, and only polling when comet isn't available.
+pub async fn run_watch(
+    plurk: Plurk,
+    id: &str,
+    interval: Duration,
+    archive: Option<&Archive>,
+    opts: &RenderOptions,
+) -> Result<(), PlurkError> {
+    let plurk_id =
+        parse_plurk_id(id).ok_or_else(|| PlurkError::InvalidUrl(format!("not a plurk: {}", id)))?;
+    let thread = plurk.get_plurk(plurk_id).await?;
+    let body = plurk.get_all_responses(plurk_id, 0).await?;
+    record(
+        archive,
+        [&thread.plurk],
+        &body.responses,
+        body.friends.values().chain([&thread.user]),
+    );
+    let p = thread.plurk;
+
+    println!(
+        "Plurk ==> https://www.plurk.com/p/{}",
+        base36_encode(p.plurk_id)
+    );
+    println!(
+        "{}",
+        opts.header(
+            &p,
+            &thread.user.display_name,
+            thread.user.name_color.as_deref()
+        )
+    );
+    println!("{}", opts.body(&opts.content(&p.content_raw, &p.content)));
+    println!("{}", opts.rule());
+    println!(
+        "Watching {} responses...ctrl+c to exit",
+        body.responses.len()
+    );
+
+    let mut watch = Watch {
+        plurk,
+        archive,
+        plurk_id,
+        seen: body.responses.len() as u64,
+        last_id: body.responses.iter().map(|r| r.id).max().unwrap_or(0),
+    };
+    let follow = async {
+        if let Err(e) = watch.comet(interval, opts).await {
+            eprintln!("Comet unavailable ({}), polling instead", e);
+        }
+        watch.poll(interval, opts).await
+    };
+    tokio::select! {
+        output = follow => output,
+        _ = signal::ctrl_c() => Ok(()),
+    }
+}